@@ -0,0 +1,196 @@
+//! Throughput benchmarks of the encoding and decoding paths.
+//!
+//! Run with `cargo bench`, optionally followed by `-- <filter>` to only run the benchmarks whose
+//! name contains the filter. This uses a minimal timing loop rather than a benchmarking
+//! framework, so it builds without any dependencies.
+
+extern crate varu64;
+
+use std::env;
+use std::io::{Cursor, Write};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use varu64::DecodeError;
+
+// How long to run each benchmark for.
+const DURATION: Duration = Duration::from_millis(500);
+
+// How many values each data set holds.
+const VALUES: usize = 1 << 14;
+
+// Run `f` repeatedly for about `DURATION`, then print the mean time per run and the throughput
+// in values per second, if it matches the filter.
+fn bench<F: FnMut()>(filter: &Option<String>, name: &str, values: usize, mut f: F) {
+    if let Some(filter) = filter.as_ref() {
+        if !name.contains(filter.as_str()) {
+            return;
+        }
+    }
+
+    f(); // Warm up.
+    let start = Instant::now();
+    let mut runs = 0u32;
+    while start.elapsed() < DURATION {
+        f();
+        runs += 1;
+    }
+
+    let per_run = start.elapsed() / runs;
+    let per_second = values as f64 / per_run.as_secs_f64();
+    println!("{:<32} {:>12.0?} per run {:>10.1} M values/s",
+             name,
+             per_run,
+             per_second / 1e6);
+}
+
+// Values below 248, which encode to a single byte.
+fn small() -> Vec<u64> {
+    (0..VALUES as u64).map(|n| n % 248).collect()
+}
+
+// Values of all encoding lengths.
+fn mixed() -> Vec<u64> {
+    (0..VALUES as u64).map(|n| n.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (n % 64)).collect()
+}
+
+// The former implementation of `encoding_length`, as a baseline.
+#[inline(never)]
+fn cascade_encoding_length(n: u64) -> usize {
+    if n < 248 {
+        1
+    } else if n < 1 << 8 {
+        2
+    } else if n < 1 << 16 {
+        3
+    } else if n < 1 << 24 {
+        4
+    } else if n < 1 << 32 {
+        5
+    } else if n < 1 << 40 {
+        6
+    } else if n < 1 << 48 {
+        7
+    } else if n < 1 << 56 {
+        8
+    } else {
+        9
+    }
+}
+
+// The former implementation of `decode`, accumulating the payload byte by byte, as a baseline.
+// It has the same signature as `decode`, since the size of the result affects the timings.
+#[inline(never)]
+fn bytewise_decode(input: &[u8]) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
+    let first = match input.first() {
+        Some(b) => *b,
+        None => return Err((DecodeError::UnexpectedEndOfInput(1), input)),
+    };
+    if first < 248 {
+        return Ok((first as u64, &input[1..]));
+    }
+
+    let length = (first & 0b0000_0111) as usize + 2;
+    let mut out: u64 = 0;
+    for i in 1..length {
+        match input.get(i) {
+            Some(b) => out = (out << 8) + *b as u64,
+            None => return Err((DecodeError::UnexpectedEndOfInput(length - i), &input[i..])),
+        }
+    }
+
+    if length > varu64::encoding_length(out) {
+        Err((DecodeError::NonCanonical(out), &input[length..]))
+    } else {
+        Ok((out, &input[length..]))
+    }
+}
+
+fn main() {
+    // Cargo passes `--bench`, any other argument is a filter.
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with("--"));
+
+    for (data, values) in [("small", small()), ("mixed", mixed())].iter() {
+        bench(&filter, &format!("encoding_length/{}", data), values.len(), || {
+            let len: usize = values.iter().map(|n| varu64::encoding_length(*n)).sum();
+            black_box(len);
+        });
+
+        bench(&filter, &format!("cascade_encoding_length/{}", data), values.len(), || {
+            let len: usize = values.iter().map(|n| cascade_encoding_length(*n)).sum();
+            black_box(len);
+        });
+
+        let mut buf = [0u8; 9];
+        bench(&filter, &format!("encode/{}", data), values.len(), || {
+            for n in values.iter() {
+                black_box(varu64::encode(*n, black_box(&mut buf[..])));
+            }
+        });
+
+        let mut out = Vec::with_capacity(values.len() * 9);
+        bench(&filter, &format!("encode_slice/{}", data), values.len(), || {
+            out.clear();
+            black_box(varu64::encode_slice(values, &mut out));
+        });
+
+        let mut encoded = Vec::new();
+        varu64::encode_slice(values, &mut encoded);
+        bench(&filter, &format!("decode/{}", data), values.len(), || {
+            let mut input = black_box(&encoded[..]);
+            while let Ok((n, tail)) = varu64::decode(input) {
+                black_box(n);
+                input = tail;
+            }
+        });
+
+        bench(&filter, &format!("bytewise_decode/{}", data), values.len(), || {
+            let mut input = black_box(&encoded[..]);
+            while let Ok((n, tail)) = bytewise_decode(input) {
+                black_box(n);
+                input = tail;
+            }
+        });
+
+        bench(&filter, &format!("decode_all/{}", data), values.len(), || {
+            black_box(varu64::decode_all(black_box(&encoded)).unwrap());
+        });
+
+        // Writing to in-memory buffers, compared to going through a temporary array and
+        // `write_all` for every value.
+        bench(&filter, &format!("write_all_array/{}", data), values.len(), || {
+            out.clear();
+            for n in values.iter() {
+                let mut buf = [0u8; 9];
+                let len = varu64::encode(*n, &mut buf[..]);
+                out.write_all(&buf[..len]).unwrap();
+            }
+            black_box(&out);
+        });
+
+        bench(&filter, &format!("encode_write/{}", data), values.len(), || {
+            out.clear();
+            for n in values.iter() {
+                varu64::encode_write(*n, &mut out).unwrap();
+            }
+            black_box(&out);
+        });
+
+        bench(&filter, &format!("encode_append/{}", data), values.len(), || {
+            out.clear();
+            for n in values.iter() {
+                varu64::encode_append(*n, &mut out);
+            }
+            black_box(&out);
+        });
+
+        let mut backing = vec![0u8; values.len() * 9];
+        bench(&filter, &format!("encode_into_cursor/{}", data), values.len(), || {
+            let mut c = Cursor::new(&mut backing[..]);
+            for n in values.iter() {
+                varu64::encode_into_cursor(*n, &mut c).unwrap();
+            }
+            black_box(c.position());
+        });
+    }
+}
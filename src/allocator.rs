@@ -0,0 +1,194 @@
+//! Variants of the allocating APIs that allocate through a custom allocator, available with
+//! the `allocator-api2` feature.
+//!
+//! These use the [`allocator-api2`](https://docs.rs/allocator-api2) crate, which mirrors the
+//! unstable `allocator_api` of the standard library on stable rust.
+
+use std::cmp::min;
+
+use allocator_api2::alloc::Allocator;
+use allocator_api2::vec::Vec;
+
+use super::nb::{Decoder, DecodeError, DecodeLimitError, MAX_ALLOC};
+
+/// State for decoding a VarU64 followed by that many bytes into a `Vec<u8, A>`.
+///
+/// This works like `nb::LengthValueDecoder`, except for the allocator.
+pub struct LengthValueDecoderIn<A: Allocator>(Inner<A>);
+
+impl<A: Allocator> LengthValueDecoderIn<A> {
+    /// Create a new `LengthValueDecoderIn`, allocating the value in `alloc`.
+    pub fn new_in(alloc: A) -> LengthValueDecoderIn<A> {
+        LengthValueDecoderIn(Inner::new(u64::MAX, alloc))
+    }
+
+    /// Decode a VarU64 from the input, then reads that many bytes into a `Vec<u8, A>`.
+    ///
+    /// Returns how many bytes have been read. A `None` is returned if more input is needed.
+    pub fn decode(&mut self, input: &[u8]) -> (usize, Option<Result<Vec<u8, A>, DecodeError>>) {
+        match self.0.decode(input) {
+            (amount, Some(Err(DecodeLimitError::NonCanonical))) => {
+                (amount, Some(Err(DecodeError::NonCanonical)))
+            }
+            (_, Some(Err(DecodeLimitError::Limit { .. }))) => unreachable!(), // no value exceeds the maximum u64
+            (amount, Some(Ok(v))) => (amount, Some(Ok(v))),
+            (amount, None) => (amount, None),
+        }
+    }
+}
+
+/// State for decoding a VarU64 followed by that many bytes into a `Vec<u8, A>`, erroring if
+/// the VarU64 is greater than a limit.
+///
+/// This works like `nb::LengthValueLimitDecoder`, except for the allocator.
+pub struct LengthValueLimitDecoderIn<A: Allocator>(Inner<A>);
+
+impl<A: Allocator> LengthValueLimitDecoderIn<A> {
+    /// Create a new `LengthValueLimitDecoderIn`, only accepting values up to length `limit`
+    /// and allocating the value in `alloc`.
+    pub fn new_in(limit: u64, alloc: A) -> LengthValueLimitDecoderIn<A> {
+        LengthValueLimitDecoderIn(Inner::new(limit, alloc))
+    }
+
+    /// Decode a VarU64 from the input, then reads that many bytes into a `Vec<u8, A>`.
+    ///
+    /// Returns how many bytes have been read. A `None` is returned if more input is needed.
+    pub fn decode(&mut self,
+                  input: &[u8])
+                  -> (usize, Option<Result<Vec<u8, A>, DecodeLimitError>>) {
+        self.0.decode(input)
+    }
+}
+
+struct Inner<A: Allocator> {
+    state: State,
+    limit: u64,
+    value: Option<Vec<u8, A>>,
+}
+
+enum State {
+    Length(Decoder),
+    Value(u64),
+}
+
+impl<A: Allocator> Inner<A> {
+    fn new(limit: u64, alloc: A) -> Inner<A> {
+        Inner {
+            state: State::Length(Decoder::new()),
+            limit,
+            value: Some(Vec::new_in(alloc)),
+        }
+    }
+
+    fn decode(&mut self,
+              mut input: &[u8])
+              -> (usize, Option<Result<Vec<u8, A>, DecodeLimitError>>) {
+        let mut total_amount = 0;
+        loop {
+            self.state = match self.state {
+                State::Length(ref mut dec) => {
+                    match dec.decode(input) {
+                        (amount, None) => return (total_amount + amount, None),
+                        (amount, Some(Err(err))) => {
+                            return (total_amount + amount, Some(Err(err.into())))
+                        }
+                        (amount, Some(Ok(len))) => {
+                            total_amount += amount;
+
+                            if len > self.limit {
+                                return (total_amount,
+                                        Some(Err(DecodeLimitError::Limit {
+                                            limit: self.limit,
+                                            actual: len,
+                                        })));
+                            }
+
+                            self.value
+                                .as_mut()
+                                .unwrap()
+                                .reserve(min(MAX_ALLOC, len as usize));
+                            input = &input[amount..];
+                            State::Value(len)
+                        }
+                    }
+                }
+
+                State::Value(len) => {
+                    if self.value.as_ref().unwrap().len() as u64 == len {
+                        return (total_amount, Some(Ok(self.value.take().unwrap())));
+                    } else if input.is_empty() {
+                        return (total_amount, None);
+                    } else {
+                        let missing = len as usize - self.value.as_ref().unwrap().len();
+                        let amount = min(missing, input.len());
+                        self.value
+                            .as_mut()
+                            .unwrap()
+                            .extend_from_slice(&input[..amount]);
+                        total_amount += amount;
+                        input = &input[amount..];
+                        State::Value(len)
+                    }
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use allocator_api2::alloc::Global;
+
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        let mut dec = LengthValueDecoderIn::new_in(Global);
+        assert_eq!(dec.decode(&[3, 1]), (2, None));
+        assert_eq!(dec.decode(&[2, 3, 4]).0, 2);
+
+        let mut dec = LengthValueDecoderIn::new_in(Global);
+        match dec.decode(&[0]) {
+            (1, Some(Ok(v))) => assert!(v.is_empty()),
+            _ => panic!(),
+        }
+
+        let mut dec = LengthValueDecoderIn::new_in(Global);
+        assert_eq!(dec.decode(&[248, 1]), (2, Some(Err(DecodeError::NonCanonical))));
+
+        let mut dec = LengthValueLimitDecoderIn::new_in(2, Global);
+        match dec.decode(&[2, 7, 8, 9]) {
+            (3, Some(Ok(v))) => assert_eq!(&v[..], &[7, 8][..]),
+            _ => panic!(),
+        }
+
+        let mut dec = LengthValueLimitDecoderIn::new_in(2, Global);
+        assert_eq!(dec.decode(&[3, 7, 8, 9]),
+                   (1, Some(Err(DecodeLimitError::Limit { limit: 2, actual: 3 }))));
+    }
+
+    quickcheck! {
+        fn test_length_value_decoder_in(val: ::std::vec::Vec<u8>, chunk_size: u8) -> bool {
+            let mut data = vec![0u8; 9];
+            let len = super::super::encode(val.len() as u64, &mut data[..]);
+            data.truncate(len);
+            data.extend_from_slice(&val);
+
+            let mut dec = LengthValueDecoderIn::new_in(Global);
+            let mut consumed = 0;
+            for chunk in data.chunks((chunk_size as usize) + 1) {
+                match dec.decode(chunk) {
+                    (amount, None) => consumed += amount,
+                    (amount, Some(Ok(v))) => {
+                        assert_eq!(&v[..], &val[..]);
+                        assert_eq!(consumed + amount, data.len());
+                        return true;
+                    }
+                    (_, Some(Err(_))) => panic!(),
+                }
+            }
+
+            panic!()
+        }
+    }
+}
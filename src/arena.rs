@@ -0,0 +1,50 @@
+//! Decoding into a [`bumpalo`](https://docs.rs/bumpalo) arena, available with the `bumpalo`
+//! feature.
+
+use bumpalo::Bump;
+use bumpalo::collections::Vec;
+
+use super::{count_encodings, decode, DecodeError};
+
+/// Decode all concatenated varu64s in the `input`, allocating the resulting slice in the
+/// arena. The slice is sized exactly, by scanning the tag bytes of the `input` up front.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts.
+pub fn decode_all_in<'a>(bump: &'a Bump, input: &[u8]) -> Result<&'a [u64], (DecodeError, usize)> {
+    let mut out = Vec::with_capacity_in(count_encodings(input), bump);
+    let mut remaining = input;
+
+    while !remaining.is_empty() {
+        match decode(remaining) {
+            Ok((n, tail)) => {
+                out.push(n);
+                remaining = tail;
+            }
+            Err((e, _)) => return Err((e, input.len() - remaining.len())),
+        }
+    }
+
+    Ok(out.into_bump_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::*;
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let bump = Bump::new();
+        assert_eq!(decode_all_in(&bump, &[]).unwrap(), &[][..]);
+        assert_eq!(decode_all_in(&bump, &[0, 248, 255, 249, 1, 0]).unwrap(),
+                   &[0, 255, 256][..]);
+        assert_eq!(decode_all_in(&bump, &[0, 248, 42]).unwrap_err(),
+                   (NonCanonical(42), 1));
+        assert_eq!(decode_all_in(&bump, &[0, 1, 249, 1]).unwrap_err(),
+                   (UnexpectedEndOfInput(1), 2));
+    }
+}
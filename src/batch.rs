@@ -0,0 +1,166 @@
+//! Bulk decoding and encoding of many values per call.
+//!
+//! The fast paths process eight bytes at a time using plain `u64` arithmetic, which works on
+//! every target and leaves vectorization to the compiler. They are taken for runs of values
+//! below 248, which dominate typical data such as postings lists and deltas; all other data
+//! goes through the scalar code.
+
+use super::{decode, encoding_length, spec, DecodeError};
+
+const LOW_SEVEN: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const HIGH: u64 = 0x8080_8080_8080_8080;
+const EIGHTS: u64 = 0x0808_0808_0808_0808;
+
+// Return a word with the most significant bit of every byte set whose byte in `w` is 248 or
+// more, i.e. is the tag of a multi-byte encoding. Adding eight to the low seven bits carries into
+// the most significant bit exactly if they are 120 or more.
+fn multi_byte_tags(w: u64) -> u64 {
+    ((w & LOW_SEVEN) + EIGHTS) & w & HIGH
+}
+
+/// Decode concatenated varu64s from the `input` into `out`, until either `out` is full or the
+/// `input` has been consumed. Returns how many values have been decoded and how many bytes of
+/// the `input` they took up.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts. The values before it have been written to `out`.
+pub fn decode_batch(input: &[u8], out: &mut [u64]) -> Result<(usize, usize), (DecodeError, usize)> {
+    let mut offset = 0;
+    let mut count = 0;
+
+    while count < out.len() && offset < input.len() {
+        if input.len() - offset >= 8 && out.len() - count >= 8 {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&input[offset..offset + 8]);
+            if multi_byte_tags(u64::from_le_bytes(word)) == 0 {
+                for (o, b) in out[count..count + 8].iter_mut().zip(word.iter()) {
+                    *o = *b as u64;
+                }
+                offset += 8;
+                count += 8;
+                continue;
+            }
+        }
+
+        match decode(&input[offset..]) {
+            Ok((n, tail)) => {
+                out[count] = n;
+                offset = input.len() - tail.len();
+                count += 1;
+            }
+            Err((e, _)) => return Err((e, offset)),
+        }
+    }
+
+    Ok((count, offset))
+}
+
+/// Encodes the `values` one after the other, appending them to `out` and returning how many
+/// bytes have been written. Produces the same bytes as `encode_slice`.
+///
+/// The values are processed in chunks of eight. The encodings of a chunk are assembled on the
+/// stack and appended to `out` with a single copy, and a chunk of values below 248 is copied
+/// without computing any lengths.
+pub fn encode_batch(values: &[u64], out: &mut Vec<u8>) -> usize {
+    let start = out.len();
+    out.reserve(values.len());
+
+    let mut buf = [0u8; 8 * 9];
+    for chunk in values.chunks(8) {
+        if chunk.iter().all(|n| *n <= spec::MAX_SINGLE_BYTE as u64) {
+            for (b, n) in buf.iter_mut().zip(chunk.iter()) {
+                *b = *n as u8;
+            }
+            out.extend_from_slice(&buf[..chunk.len()]);
+        } else {
+            let mut len = 0;
+            for n in chunk.iter() {
+                len += write_encoding(*n, &mut buf[len..]);
+            }
+            out.extend_from_slice(&buf[..len]);
+        }
+    }
+
+    out.len() - start
+}
+
+// Write the encoding of n to the start of buf with a single copy of its big-endian bytes,
+// returning its length.
+fn write_encoding(n: u64, buf: &mut [u8]) -> usize {
+    let len = encoding_length(n);
+    if len == 1 {
+        buf[0] = n as u8;
+    } else {
+        buf[0] = spec::TAGS[len - 2];
+        buf[1..len].copy_from_slice(&n.to_be_bytes()[9 - len..]);
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{decode_all, encode_append, encode_slice};
+    use super::super::DecodeError::*;
+    use super::super::tests::{spread, spread_all};
+
+    #[test]
+    fn fixtures() {
+        assert_eq!(multi_byte_tags(u64::from_le_bytes([0, 127, 128, 247, 248, 249, 255, 120])),
+                   0x0080_8080_0000_0000);
+
+        let input = [1, 2, 3, 4, 5, 6, 7, 8, 9, 249, 1, 44, 247, 0];
+        let mut out = [0u64; 16];
+        assert_eq!(decode_batch(&input, &mut out), Ok((12, 14)));
+        assert_eq!(&out[..12], &[1, 2, 3, 4, 5, 6, 7, 8, 9, 300, 247, 0]);
+        assert_eq!(decode_batch(&input, &mut out[..9]), Ok((9, 9)));
+        assert_eq!(decode_batch(&input, &mut []), Ok((0, 0)));
+
+        let input = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 248, 1];
+        assert_eq!(decode_batch(&input, &mut out), Err((NonCanonical(1), 10)));
+        assert_eq!(decode_batch(&input[..11], &mut out), Err((UnexpectedEndOfInput(1), 10)));
+    }
+
+    #[test]
+    fn test_encode_batch() {
+        let mut out = vec![42];
+        assert_eq!(encode_batch(&[1, 2, 3, 4, 5, 6, 7, 8, 300, 248, 0], &mut out), 14);
+        assert_eq!(out, vec![42, 1, 2, 3, 4, 5, 6, 7, 8, 249, 1, 44, 248, 248, 0]);
+
+        out.clear();
+        assert_eq!(encode_batch(&[u64::MAX, 0], &mut out), 10);
+        assert_eq!(out, vec![255, 255, 255, 255, 255, 255, 255, 255, 255, 0]);
+    }
+
+    quickcheck! {
+        fn test_encode_batch_agrees(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut expected = Vec::new();
+            encode_slice(&values, &mut expected);
+            let mut out = Vec::new();
+            encode_batch(&values, &mut out) == out.len() && out == expected
+        }
+
+        fn test_decode_batch(values: Vec<u64>, small: Vec<u8>, len: usize) -> bool {
+            let mut data = Vec::new();
+            for (n, b) in values.iter().zip(small.iter().cycle()) {
+                encode_append(spread(*n), &mut data);
+                for _ in 0..*b % 16 {
+                    encode_append(*b as u64 % 248, &mut data);
+                }
+            }
+
+            let expected = decode_all(&data).unwrap();
+            let len = if expected.is_empty() { 0 } else { len % (expected.len() + 1) };
+            let mut out = vec![0u64; len];
+            match decode_batch(&data, &mut out) {
+                Ok((count, consumed)) => {
+                    count == len && out[..] == expected[..len] &&
+                    decode_all(&data[..consumed]) == Ok(out.clone())
+                }
+                Err(_) => false,
+            }
+        }
+    }
+}
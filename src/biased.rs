@@ -0,0 +1,247 @@
+//! Encodings that transform a value before encoding it as a varu64: shifting it by a constant,
+//! packing tag bits into it, or mapping signed integers to unsigned ones.
+
+use std::num::NonZeroU64;
+
+use super::{decode, encode, encoding_length, try_encode, DecodeError, EncodeError};
+
+/// Encodes `n - 1` into the output buffer, returning how many bytes have been written. This
+/// saves a byte for 248, as well as for every power of 256 from 2^8 to 2^56.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_nonzero(n: NonZeroU64, out: &mut [u8]) -> usize {
+    encode(n.get() - 1, out)
+}
+
+/// Decode a number encoded by `encode_nonzero` from the `input` buffer, returning it and the
+/// remaining bytes.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, with canonicality referring to the encoded
+/// `n - 1`. An encoding of `u64::MAX` has no corresponding `NonZeroU64` and results in a
+/// `ValueOutOfRange` error.
+pub fn decode_nonzero(input: &[u8]) -> Result<(NonZeroU64, &[u8]), (DecodeError, &[u8])> {
+    let (n, tail) = decode(input)?;
+    match n.checked_add(1) {
+        Some(n) => Ok((NonZeroU64::new(n).unwrap(), tail)),
+        None => Err((DecodeError::ValueOutOfRange(n), tail)),
+    }
+}
+
+/// Encodes `None` as `0` and `Some(n)` as `n + 1` into the output buffer, returning how many
+/// bytes have been written.
+///
+/// # Errors
+/// `Some(u64::MAX)` can not be encoded and results in a `ValueOutOfRange` error. A buffer that
+/// is too small results in a `BufferTooSmall` error. In both cases, the buffer is left
+/// unchanged.
+pub fn encode_option(value: Option<u64>, out: &mut [u8]) -> Result<usize, EncodeError> {
+    match value {
+        None => try_encode(0, out),
+        Some(n) => {
+            match n.checked_add(1) {
+                Some(biased) => try_encode(biased, out),
+                None => Err(EncodeError::ValueOutOfRange(n)),
+            }
+        }
+    }
+}
+
+/// Decode a value encoded by `encode_option` from the `input` buffer, returning it and the
+/// remaining bytes.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, with canonicality referring to the encoded number.
+pub fn decode_option(input: &[u8]) -> Result<(Option<u64>, &[u8]), (DecodeError, &[u8])> {
+    let (n, tail) = decode(input)?;
+    Ok((n.checked_sub(1), tail))
+}
+
+/// Encodes `value << tag_bits | tag` into the output buffer, returning how many bytes have been
+/// written.
+///
+/// # Errors
+/// A `value` that does not fit into `64 - tag_bits` bits results in a `ValueOutOfRange` error
+/// containing it. A buffer that is too small results in a `BufferTooSmall` error. In both
+/// cases, the buffer is left unchanged.
+///
+/// # Panics
+/// Panics if `tag_bits` is 64 or more, or if the `tag` does not fit into `tag_bits` bits.
+pub fn encode_tagged(value: u64,
+                     tag: u64,
+                     tag_bits: u32,
+                     out: &mut [u8])
+                     -> Result<usize, EncodeError> {
+    assert!(tag_bits < 64, "tag_bits must be less than 64");
+    assert!(tag >> tag_bits == 0, "tag does not fit into tag_bits bits");
+
+    if value.leading_zeros() < tag_bits {
+        return Err(EncodeError::ValueOutOfRange(value));
+    }
+    try_encode(value << tag_bits | tag, out)
+}
+
+/// Decode a value encoded by `encode_tagged` from the `input` buffer, returning the value, the
+/// tag and the remaining bytes.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, with canonicality referring to the encoded number.
+///
+/// # Panics
+/// Panics if `tag_bits` is 64 or more.
+pub fn decode_tagged(input: &[u8],
+                     tag_bits: u32)
+                     -> Result<(u64, u64, &[u8]), (DecodeError, &[u8])> {
+    assert!(tag_bits < 64, "tag_bits must be less than 64");
+
+    let (n, tail) = decode(input)?;
+    Ok((n >> tag_bits, n & ((1 << tag_bits) - 1), tail))
+}
+
+/// Map `n` to a `u64` such that numbers of small magnitude map to small numbers: 0, -1, 1, -2,
+/// 2, ... map to 0, 1, 2, 3, 4, ...
+pub fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+/// The inverse of `zigzag`.
+pub fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+/// Return how many bytes the encoding of `n` by `encode_i64` will take up.
+pub fn encoding_length_i64(n: i64) -> usize {
+    encoding_length(zigzag(n))
+}
+
+/// Encodes `zigzag(n)` into the output buffer, returning how many bytes have been written.
+///
+/// The encoding does not preserve the order of numbers, use `key::encode_i64` for that.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_i64(n: i64, out: &mut [u8]) -> usize {
+    encode(zigzag(n), out)
+}
+
+/// Decode a number encoded by `encode_i64` from the `input` buffer, returning it and the
+/// remaining bytes.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, with canonicality referring to the encoded
+/// `zigzag(n)`, so every number has exactly one encoding.
+pub fn decode_i64(input: &[u8]) -> Result<(i64, &[u8]), (DecodeError, &[u8])> {
+    let (n, tail) = decode(input)?;
+    Ok((unzigzag(n), tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU64;
+
+    use super::*;
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let mut buf = [0u8; 9];
+        assert_eq!(encode_nonzero(NonZeroU64::new(248).unwrap(), &mut buf[..]), 1);
+        assert_eq!(buf[0], 247);
+        assert_eq!(decode_nonzero(&[0, 7]), Ok((NonZeroU64::new(1).unwrap(), &[7][..])));
+        assert_eq!(decode_nonzero(&[248, 1]), Err((NonCanonical(1), &[][..])));
+        assert_eq!(decode_nonzero(&[255, 255, 255, 255, 255, 255, 255, 255, 255]),
+                   Err((ValueOutOfRange(u64::MAX), &[][..])));
+    }
+
+    #[test]
+    fn test_option() {
+        let mut buf = [0u8; 9];
+        assert_eq!(encode_option(None, &mut buf[..]), Ok(1));
+        assert_eq!(buf[0], 0);
+        assert_eq!(encode_option(Some(0), &mut buf[..]), Ok(1));
+        assert_eq!(buf[0], 1);
+        assert_eq!(encode_option(Some(u64::MAX), &mut buf[..]),
+                   Err(EncodeError::ValueOutOfRange(u64::MAX)));
+        assert_eq!(encode_option(Some(255), &mut buf[..1]), Err(EncodeError::BufferTooSmall(3)));
+
+        assert_eq!(decode_option(&[0, 7]), Ok((None, &[7][..])));
+        assert_eq!(decode_option(&[249, 1, 0]), Ok((Some(255), &[][..])));
+        assert_eq!(decode_option(&[248, 0]), Err((NonCanonical(0), &[][..])));
+    }
+
+    #[test]
+    fn test_tagged() {
+        let mut buf = [0u8; 9];
+        assert_eq!(encode_tagged(5, 2, 2, &mut buf[..]), Ok(1));
+        assert_eq!(buf[0], 22);
+        assert_eq!(decode_tagged(&buf[..1], 2), Ok((5, 2, &[][..])));
+        assert_eq!(decode_tagged(&[249, 1, 0], 0), Ok((256, 0, &[][..])));
+
+        assert_eq!(encode_tagged(1 << 62, 0, 2, &mut buf[..]),
+                   Err(EncodeError::ValueOutOfRange(1 << 62)));
+        assert_eq!(encode_tagged(1 << 61, 3, 2, &mut buf[..]), Ok(9));
+        assert_eq!(decode_tagged(&buf, 2), Ok((1 << 61, 3, &[][..])));
+    }
+
+    #[test]
+    fn test_i64() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+        assert_eq!(zigzag(i64::MAX), u64::MAX - 1);
+
+        let mut buf = [0u8; 9];
+        assert_eq!(encode_i64(-124, &mut buf[..]), 1);
+        assert_eq!(buf[0], 247);
+        assert_eq!(encoding_length_i64(124), 2);
+        assert_eq!(encode_i64(124, &mut buf[..]), 2);
+        assert_eq!(&buf[..2], &[248, 248]);
+        assert_eq!(decode_i64(&[3, 7]), Ok((-2, &[7][..])));
+        assert_eq!(decode_i64(&[248, 3]), Err((NonCanonical(3), &[][..])));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tagged_tag_too_large() {
+        let _ = encode_tagged(0, 4, 2, &mut [0u8; 9]);
+    }
+
+    quickcheck! {
+        fn test_tagged_roundtrip(value: u64, tag: u64, tag_bits: u8, shift: u8) -> bool {
+            let tag_bits = (tag_bits % 64) as u32;
+            let value = value.rotate_left(shift as u32) >> tag_bits;
+            let tag = tag & ((1 << tag_bits) - 1);
+            let mut buf = [0u8; 9];
+            let len = encode_tagged(value, tag, tag_bits, &mut buf[..]).unwrap();
+            decode_tagged(&buf[..len], tag_bits) == Ok((value, tag, &[][..]))
+        }
+
+        fn test_option_roundtrip(value: Option<u64>, shift: u8) -> bool {
+            let value = value.map(|n| n.rotate_left(shift as u32));
+            let mut buf = [0u8; 9];
+            match encode_option(value, &mut buf[..]) {
+                Ok(len) => decode_option(&buf[..len]) == Ok((value, &[][..])),
+                Err(e) => value == Some(u64::MAX) && e == EncodeError::ValueOutOfRange(u64::MAX),
+            }
+        }
+
+        fn test_i64_roundtrip(n: i64, shift: u8) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let mut buf = [0u8; 9];
+            let len = encode_i64(n, &mut buf[..]);
+            len == encoding_length_i64(n) && decode_i64(&buf[..len]) == Ok((n, &[][..]))
+        }
+
+        fn test_nonzero_roundtrip(n: u64, shift: u8) -> bool {
+            let n = match NonZeroU64::new(n.rotate_left(shift as u32)) {
+                Some(n) => n,
+                None => return true,
+            };
+            let mut buf = [0u8; 9];
+            let len = encode_nonzero(n, &mut buf[..]);
+            decode_nonzero(&buf[..len]) == Ok((n, &[][..]))
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Arbitrary-precision unsigned integers, available with the `num-bigint` feature.
+//!
+//! This is an extension and not part of the varu64 spec. A `BigUint` is encoded as a varu64
+//! holding the number of 64 bit limbs, followed by the limbs as varu64s, least significant limb
+//! first. The most significant limb must not be zero, so zero is encoded as a limb count of
+//! zero, and every number has exactly one encoding.
+
+use num_bigint::BigUint;
+
+use super::{encode_append, decode, DecodeError};
+
+/// Encodes `n`, appending it to `out` and returning how many bytes have been written.
+pub fn encode_biguint(n: &BigUint, out: &mut Vec<u8>) -> usize {
+    let mut written = encode_append(n.iter_u64_digits().len() as u64, out);
+    for limb in n.iter_u64_digits() {
+        written += encode_append(limb, out);
+    }
+    written
+}
+
+/// Decode a `BigUint` from the `input` buffer, returning the number and the remaining bytes.
+///
+/// # Errors
+/// Errors in the limb count or in a limb are reported as by `decode`. If the most significant
+/// limb is zero, a `NonCanonical(0)` error is returned.
+pub fn decode_biguint(input: &[u8]) -> Result<(BigUint, &[u8]), (DecodeError, &[u8])> {
+    let (count, mut remaining) = decode(input)?;
+
+    // Every limb takes up at least one byte, so this does not trust the count for allocation.
+    let mut limbs = Vec::with_capacity(if count as usize > remaining.len() {
+                                           remaining.len()
+                                       } else {
+                                           count as usize
+                                       });
+    for _ in 0..count {
+        let (limb, tail) = decode(remaining)?;
+        limbs.push(limb);
+        remaining = tail;
+    }
+
+    if limbs.last() == Some(&0) {
+        return Err((DecodeError::NonCanonical(0), remaining));
+    }
+
+    Ok((BigUint::from_slice(&u64_to_u32_digits(&limbs)), remaining))
+}
+
+// Split 64 bit limbs into the 32 bit digits expected by `BigUint::from_slice`.
+fn u64_to_u32_digits(limbs: &[u64]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(limbs.len() * 2);
+    for limb in limbs.iter() {
+        digits.push(*limb as u32);
+        digits.push((*limb >> 32) as u32);
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        let mut out = Vec::new();
+        assert_eq!(encode_biguint(&BigUint::from(0u8), &mut out), 1);
+        assert_eq!(out, vec![0]);
+
+        let n = (BigUint::from(3u8) << 64) + BigUint::from(300u16);
+        out.clear();
+        assert_eq!(encode_biguint(&n, &mut out), 5);
+        assert_eq!(out, vec![2, 249, 1, 44, 3]);
+        assert_eq!(decode_biguint(&out).unwrap(), (n, &[][..]));
+
+        assert_eq!(decode_biguint(&[2, 1, 0, 7]).unwrap_err(),
+                   (DecodeError::NonCanonical(0), &[7][..]));
+        assert_eq!(decode_biguint(&[255, 1]).unwrap_err(),
+                   (DecodeError::UnexpectedEndOfInput(7), &[][..]));
+        assert_eq!(decode_biguint(&[2, 1]).unwrap_err(),
+                   (DecodeError::UnexpectedEndOfInput(1), &[][..]));
+    }
+
+    quickcheck! {
+        fn test_roundtrip(digits: Vec<u32>, tail: Vec<u8>) -> bool {
+            let n = BigUint::from_slice(&digits);
+            let mut out = Vec::new();
+            let len = encode_biguint(&n, &mut out);
+            out.extend_from_slice(&tail);
+
+            len == out.len() - tail.len() && decode_biguint(&out).unwrap() == (n, &tail[..])
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Frame-of-reference encoding of blocks of values.
+//!
+//! A block is encoded as a varu64 holding the number of values, a varu64 holding the smallest
+//! value (the reference), and then the difference of each value to the reference as a varu64.
+//! Values that lie close together, but in no particular order, thus take up few bytes each.
+//!
+//! The reference must be the smallest value of the block, or zero for an empty block, so every
+//! block has exactly one encoding.
+//!
+//! The adaptive block codec instead chooses the smallest of several encodings for each block,
+//! see `encode_adaptive_block`. It does not consider the `simple8b` format: that format accepts
+//! any packing of the values into words, so the decoder could not check that a block is encoded
+//! in the one shortest way without packing the values again, and it cannot hold values of 2^60
+//! or more. Dense blocks of small values should use `simple8b` directly.
+
+use std::cmp::max;
+
+use super::{decode, encode_append, encode_slice, encoding_length, DecodeError};
+
+/// Encodes the `values` as a block, appending it to `out` and returning how many bytes have been
+/// written.
+pub fn for_encode_block(values: &[u64], out: &mut Vec<u8>) -> usize {
+    let reference = values.iter().cloned().min().unwrap_or(0);
+
+    let mut written = encode_append(values.len() as u64, out);
+    written += encode_append(reference, out);
+    for n in values.iter() {
+        written += encode_append(n - reference, out);
+    }
+    written
+}
+
+/// Decode a block from the `input` buffer, returning its values and the remaining bytes.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts. A difference that would take a value beyond
+/// `u64::MAX` results in a `ValueOutOfRange` error containing the difference. If the reference
+/// is not the smallest value, a `NonCanonical` error containing the reference is returned, with
+/// the offset of the reference.
+pub fn for_decode_block(input: &[u8]) -> Result<(Vec<u64>, &[u8]), (DecodeError, usize)> {
+    let (count, tail) = decode(input).map_err(|(e, _)| (e, 0))?;
+    let reference_offset = input.len() - tail.len();
+    let (reference, mut remaining) = decode(tail).map_err(|(e, _)| (e, reference_offset))?;
+
+    // Every value takes up at least one byte, so this does not trust the count for allocation.
+    let mut values = Vec::with_capacity(if count as usize > remaining.len() {
+                                            remaining.len()
+                                        } else {
+                                            count as usize
+                                        });
+    let mut has_reference = false;
+    for _ in 0..count {
+        let offset = input.len() - remaining.len();
+        let (difference, tail) = decode(remaining).map_err(|(e, _)| (e, offset))?;
+        let n = reference
+            .checked_add(difference)
+            .ok_or((DecodeError::ValueOutOfRange(difference), offset))?;
+        has_reference |= difference == 0;
+        values.push(n);
+        remaining = tail;
+    }
+
+    if !has_reference && (count > 0 || reference != 0) {
+        return Err((DecodeError::NonCanonical(reference), reference_offset));
+    }
+
+    Ok((values, remaining))
+}
+
+// The header bytes of the adaptive block codec. Headers from `ADAPTIVE_FIXED + 1` to
+// `ADAPTIVE_FIXED + 8` indicate fixed-width storage with one to eight bytes per value.
+const ADAPTIVE_VARU64: u8 = 0;
+const ADAPTIVE_DELTA: u8 = 1;
+const ADAPTIVE_FIXED: u8 = 1;
+
+// Choose the header of the smallest encoding of the values, preferring smaller headers.
+fn adaptive_header(values: &[u64]) -> u8 {
+    let mut best = (values.iter().map(|n| encoding_length(*n)).sum::<usize>(), ADAPTIVE_VARU64);
+
+    if values.windows(2).all(|w| w[0] <= w[1]) {
+        let mut previous = 0;
+        let delta = values
+            .iter()
+            .map(|n| {
+                     let len = encoding_length(n - previous);
+                     previous = *n;
+                     len
+                 })
+            .sum();
+        if delta < best.0 {
+            best = (delta, ADAPTIVE_DELTA);
+        }
+    }
+
+    let largest = values.iter().cloned().max().unwrap_or(0);
+    let width = max(1, 8 - largest.leading_zeros() as usize / 8);
+    if width * values.len() < best.0 {
+        best = (width * values.len(), ADAPTIVE_FIXED + width as u8);
+    }
+
+    best.1
+}
+
+/// Encodes the `values` as an adaptive block, appending it to `out` and returning how many bytes
+/// have been written.
+///
+/// The block starts with a header byte recording the chosen encoding, followed by the number of
+/// values as a varu64. The values follow either as varu64s (header 0), as varu64s of the
+/// differences to their predecessors if they are sorted (header 1, see the `delta` module), or
+/// in big-endian byteorder with a fixed width of `header - 1` bytes, just wide enough for the
+/// largest value (headers 2 to 9). Whichever is shortest is chosen, the smaller header on a tie,
+/// so every block has exactly one encoding. High-entropy data thus never takes up more than its
+/// fixed-width encoding plus the header.
+pub fn encode_adaptive_block(values: &[u64], out: &mut Vec<u8>) -> usize {
+    let start = out.len();
+    let header = adaptive_header(values);
+    out.push(header);
+    encode_append(values.len() as u64, out);
+
+    match header {
+        ADAPTIVE_VARU64 => {
+            encode_slice(values, out);
+        }
+        ADAPTIVE_DELTA => {
+            let mut previous = 0;
+            for n in values.iter() {
+                encode_append(n - previous, out);
+                previous = *n;
+            }
+        }
+        _ => {
+            let width = (header - ADAPTIVE_FIXED) as usize;
+            for n in values.iter() {
+                out.extend_from_slice(&n.to_be_bytes()[8 - width..]);
+            }
+        }
+    }
+
+    out.len() - start
+}
+
+/// Decode an adaptive block from the `input` buffer, returning its values and the remaining
+/// bytes.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts. A header above 9 results in a `ValueOutOfRange` error
+/// containing it. If the block is not encoded in the shortest way, a `NonCanonical` error
+/// containing the header is returned, with offset zero. A difference that would take a value
+/// beyond `u64::MAX` results in a `ValueOutOfRange` error containing the difference. A
+/// truncated fixed-width value results in an `UnexpectedEndOfInput` error with the number of
+/// bytes missing from it.
+pub fn decode_adaptive_block(input: &[u8]) -> Result<(Vec<u64>, &[u8]), (DecodeError, usize)> {
+    let header = match input.first() {
+        Some(header) if *header <= ADAPTIVE_FIXED + 8 => *header,
+        Some(header) => return Err((DecodeError::ValueOutOfRange(*header as u64), 0)),
+        None => return Err((DecodeError::UnexpectedEndOfInput(1), 0)),
+    };
+    let (count, mut remaining) = decode(&input[1..]).map_err(|(e, _)| (e, 1))?;
+
+    // Every value takes up at least one byte, so this does not trust the count for allocation.
+    let mut values = Vec::with_capacity(if count as usize > remaining.len() {
+                                            remaining.len()
+                                        } else {
+                                            count as usize
+                                        });
+    if header == ADAPTIVE_VARU64 || header == ADAPTIVE_DELTA {
+        let mut previous = 0u64;
+        for _ in 0..count {
+            let offset = input.len() - remaining.len();
+            let (n, tail) = decode(remaining).map_err(|(e, _)| (e, offset))?;
+            if header == ADAPTIVE_DELTA {
+                previous = previous
+                    .checked_add(n)
+                    .ok_or((DecodeError::ValueOutOfRange(n), offset))?;
+                values.push(previous);
+            } else {
+                values.push(n);
+            }
+            remaining = tail;
+        }
+    } else {
+        let width = (header - ADAPTIVE_FIXED) as usize;
+        for _ in 0..count {
+            if remaining.len() < width {
+                return Err((DecodeError::UnexpectedEndOfInput(width - remaining.len()),
+                            input.len() - remaining.len()));
+            }
+            let mut word = [0u8; 8];
+            word[8 - width..].copy_from_slice(&remaining[..width]);
+            values.push(u64::from_be_bytes(word));
+            remaining = &remaining[width..];
+        }
+    }
+
+    if adaptive_header(&values) != header {
+        return Err((DecodeError::NonCanonical(header as u64), 0));
+    }
+
+    Ok((values, remaining))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::tests::{spread, spread_all};
+
+    #[test]
+    fn fixtures() {
+        let mut out = Vec::new();
+        assert_eq!(for_encode_block(&[1010, 1000, 1250], &mut out), 8);
+        assert_eq!(out, vec![3, 249, 3, 232, 10, 0, 248, 250]);
+        out.push(7);
+        assert_eq!(for_decode_block(&out), Ok((vec![1010, 1000, 1250], &[7][..])));
+
+        out.clear();
+        assert_eq!(for_encode_block(&[], &mut out), 2);
+        assert_eq!(out, vec![0, 0]);
+        assert_eq!(for_decode_block(&out), Ok((vec![], &[][..])));
+
+        assert_eq!(for_decode_block(&[0, 5]), Err((NonCanonical(5), 1)));
+        assert_eq!(for_decode_block(&[2, 5, 1, 2]), Err((NonCanonical(5), 1)));
+        assert_eq!(for_decode_block(&[1, 5, 248, 1]), Err((NonCanonical(1), 2)));
+        assert_eq!(for_decode_block(&[2, 5, 0]), Err((UnexpectedEndOfInput(1), 3)));
+        assert_eq!(for_decode_block(&[1, 255, 255, 255, 255, 255, 255, 255, 255, 255, 1]),
+                   Err((ValueOutOfRange(1), 10)));
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let mut out = Vec::new();
+        assert_eq!(encode_adaptive_block(&[3, 1, 2], &mut out), 5);
+        assert_eq!(out, vec![0, 3, 3, 1, 2]);
+
+        out.clear();
+        assert_eq!(encode_adaptive_block(&[1000, 1001, 1003], &mut out), 7);
+        assert_eq!(out, vec![1, 3, 249, 3, 232, 1, 2]);
+
+        out.clear();
+        let values = [0xfedc_ba98, 0x1234_5678, 0xffff_ffff];
+        assert_eq!(encode_adaptive_block(&values, &mut out), 14);
+        assert_eq!(&out[..6], &[5, 3, 0xfe, 0xdc, 0xba, 0x98]);
+        out.push(7);
+        assert_eq!(decode_adaptive_block(&out), Ok((values.to_vec(), &[7][..])));
+
+        assert_eq!(decode_adaptive_block(&[]), Err((UnexpectedEndOfInput(1), 0)));
+        assert_eq!(decode_adaptive_block(&[10, 0]), Err((ValueOutOfRange(10), 0)));
+        assert_eq!(decode_adaptive_block(&[2, 2, 1, 2]), Err((NonCanonical(2), 0)));
+        assert_eq!(decode_adaptive_block(&[1, 2, 3, 1]), Err((NonCanonical(1), 0)));
+        assert_eq!(decode_adaptive_block(&[3, 2, 1, 2, 3]), Err((UnexpectedEndOfInput(1), 4)));
+        assert_eq!(decode_adaptive_block(&[1, 2, 255, 255, 255, 255, 255, 255, 255, 255, 255, 1]),
+                   Err((ValueOutOfRange(1), 11)));
+    }
+
+    quickcheck! {
+        fn test_adaptive_roundtrip(values: Vec<u64>, shift: u8, sorted: bool) -> bool {
+            // Shifting the spread out values yields every fixed width.
+            let mut values: Vec<u64> = values
+                .iter()
+                .map(|n| spread(*n) >> (shift % 64))
+                .collect();
+            if sorted {
+                values.sort();
+            }
+            let mut out = Vec::new();
+            let len = encode_adaptive_block(&values, &mut out);
+
+            len == out.len() && len <= 2 + 9 + values.len() * 8 &&
+            decode_adaptive_block(&out) == Ok((values, &[][..]))
+        }
+
+        fn test_roundtrip(values: Vec<u64>, tail: Vec<u8>) -> bool {
+            let values = spread_all(&values);
+            let mut out = Vec::new();
+            let len = for_encode_block(&values, &mut out);
+            out.extend_from_slice(&tail);
+
+            len == out.len() - tail.len() && for_decode_block(&out) == Ok((values, &tail[..]))
+        }
+    }
+}
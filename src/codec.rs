@@ -0,0 +1,166 @@
+//! Traits for composing varu64 with other encodings.
+//!
+//! Both traits are object-safe, so protocol types can hold and nest `dyn Encodable` and
+//! `dyn Decodable` values.
+
+use std::io;
+
+use super::{decode_count, encode, encoding_length, DecodeError, VarU64};
+
+/// A value with a binary encoding.
+pub trait Encodable {
+    /// Return how many bytes the encoding takes up.
+    fn encoding_length(&self) -> usize;
+
+    /// Encode into the output buffer, returning how many bytes have been written.
+    ///
+    /// # Panics
+    /// Panics if the buffer is shorter than `encoding_length`.
+    fn encode_into(&self, out: &mut [u8]) -> usize;
+
+    /// Encode into the writer, returning how many bytes have been written.
+    ///
+    /// The default implementation encodes into a temporary buffer and writes it with
+    /// `write_all`.
+    fn encode_write(&self, w: &mut dyn io::Write) -> io::Result<usize> {
+        let mut buf = vec![0u8; self.encoding_length()];
+        let len = self.encode_into(&mut buf);
+        w.write_all(&buf[..len]).map(|_| len)
+    }
+}
+
+/// A value that can be decoded from a binary encoding.
+pub trait Decodable {
+    /// Everything that can go wrong when decoding.
+    type Error;
+
+    /// Decode from the start of the `input`, overwriting `self` and returning how many bytes
+    /// were read. On error, `self` is left unchanged.
+    fn decode_into(&mut self, input: &[u8]) -> Result<usize, Self::Error>;
+
+    /// Decode a new value from the start of the `input`, returning it and how many bytes were
+    /// read.
+    fn decode_new(input: &[u8]) -> Result<(Self, usize), Self::Error>
+        where Self: Default + Sized
+    {
+        let mut value = Self::default();
+        let len = value.decode_into(input)?;
+        Ok((value, len))
+    }
+}
+
+impl Encodable for u64 {
+    fn encoding_length(&self) -> usize {
+        encoding_length(*self)
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> usize {
+        encode(*self, out)
+    }
+
+    fn encode_write(&self, w: &mut dyn io::Write) -> io::Result<usize> {
+        let mut tmp = [0u8; 9];
+        let len = encode(*self, &mut tmp[..]);
+        w.write_all(&tmp[..len]).map(|_| len)
+    }
+}
+
+impl Decodable for u64 {
+    type Error = DecodeError;
+
+    fn decode_into(&mut self, input: &[u8]) -> Result<usize, DecodeError> {
+        let (n, len) = decode_count(input).map_err(|(e, _)| e)?;
+        *self = n;
+        Ok(len)
+    }
+}
+
+impl Encodable for VarU64 {
+    fn encoding_length(&self) -> usize {
+        self.0.encoding_length()
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> usize {
+        self.0.encode_into(out)
+    }
+
+    fn encode_write(&self, w: &mut dyn io::Write) -> io::Result<usize> {
+        self.0.encode_write(w)
+    }
+}
+
+impl Decodable for VarU64 {
+    type Error = DecodeError;
+
+    fn decode_into(&mut self, input: &[u8]) -> Result<usize, DecodeError> {
+        self.0.decode_into(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A composite type nesting other encodables, as a downstream crate would write it.
+    #[derive(Debug, Default, PartialEq)]
+    struct Header {
+        seq: u64,
+        len: VarU64,
+    }
+
+    impl Encodable for Header {
+        fn encoding_length(&self) -> usize {
+            self.seq.encoding_length() + self.len.encoding_length()
+        }
+
+        fn encode_into(&self, out: &mut [u8]) -> usize {
+            let len = self.seq.encode_into(out);
+            len + self.len.encode_into(&mut out[len..])
+        }
+    }
+
+    impl Decodable for Header {
+        type Error = DecodeError;
+
+        fn decode_into(&mut self, input: &[u8]) -> Result<usize, DecodeError> {
+            let (seq, seq_len) = u64::decode_new(input)?;
+            let (len, len_len) = VarU64::decode_new(&input[seq_len..])?;
+            *self = Header { seq, len };
+            Ok(seq_len + len_len)
+        }
+    }
+
+    #[test]
+    fn fixtures() {
+        let header = Header {
+            seq: 300,
+            len: VarU64(7),
+        };
+        let parts: [&dyn Encodable; 2] = [&header, &5u64];
+        let mut out = Vec::new();
+        for part in parts.iter() {
+            part.encode_write(&mut out).unwrap();
+        }
+        assert_eq!(out, vec![249, 1, 44, 7, 5]);
+
+        let mut decoded = Header::default();
+        {
+            let dynamic: &mut dyn Decodable<Error = DecodeError> = &mut decoded;
+            assert_eq!(dynamic.decode_into(&out), Ok(4));
+        }
+        assert_eq!(decoded, header);
+
+        let mut n = 3u64;
+        assert_eq!(n.decode_into(&[248, 1]), Err(DecodeError::NonCanonical(1)));
+        assert_eq!(n, 3);
+    }
+
+    quickcheck! {
+        fn test_u64(n: u64, shift: u8) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let mut buf = [0u8; 9];
+            let len = n.encode_into(&mut buf[..]);
+            len == n.encoding_length() && u64::decode_new(&buf[..]) == Ok((n, len))
+        }
+    }
+}
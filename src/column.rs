@@ -0,0 +1,116 @@
+//! A self-describing block format for persisting sequences of numbers.
+//!
+//! A block consists of a header of three varu64s, the format version (currently
+//! `BLOCK_VERSION`), the number of values and the length of the payload in bytes, followed by
+//! the payload: the values as concatenated varu64s. Storing the payload length allows skipping
+//! a block without decoding it.
+
+use std::io::{self, Read};
+
+use super::{decode_all, encode_append, encode_slice};
+use super::source::{decode_from, ReadSource};
+
+/// The version of the block format written by `write_block`.
+pub const BLOCK_VERSION: u64 = 0;
+
+/// Write the `values` as a block, returning how many bytes have been written.
+///
+/// IO errors are handled as described in the [crate docs](../index.html#io).
+pub fn write_block<W: io::Write>(values: &[u64], mut w: W) -> io::Result<usize> {
+    let mut payload = Vec::new();
+    encode_slice(values, &mut payload);
+
+    let mut block = Vec::with_capacity(27 + payload.len());
+    encode_append(BLOCK_VERSION, &mut block);
+    encode_append(values.len() as u64, &mut block);
+    encode_append(payload.len() as u64, &mut block);
+    block.extend_from_slice(&payload);
+
+    w.write_all(&block)?;
+    Ok(block.len())
+}
+
+/// Read a block written by `write_block`, returning its values.
+///
+/// # Errors
+/// A reader that ends within the block results in an error of kind `UnexpectedEof`. An
+/// unsupported version, invalid varu64s, and a payload that does not hold exactly the number of
+/// values given in the header result in an error of kind `InvalidData`. The payload is read
+/// incrementally, so a corrupt length can not cause a huge allocation up front.
+///
+/// The header is read one byte per call to `read`, so unbuffered readers such as files should be
+/// wrapped in a `BufReader`.
+///
+/// IO errors are handled as described in the [crate docs](../index.html#io).
+pub fn read_block<R: io::Read>(mut r: R) -> io::Result<Vec<u64>> {
+    let mut header = [0u64; 3];
+    for field in header.iter_mut() {
+        *field = match decode_from(&mut ReadSource(&mut r))? {
+            Some(n) => n,
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+    }
+    let [version, count, len] = header;
+
+    if version != BLOCK_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("Unsupported block version {}", version)));
+    }
+
+    let mut payload = Vec::new();
+    (&mut r).take(len).read_to_end(&mut payload)?;
+    if (payload.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let values = decode_all(&payload).map_err(|(e, offset)| {
+        io::Error::new(io::ErrorKind::InvalidData,
+                       format!("Invalid block payload at offset {}: {}", offset, e))
+    })?;
+    if values.len() as u64 != count {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("Block holds {} values, but its header claims {}",
+                                          values.len(),
+                                          count)));
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let mut out = Vec::new();
+        assert_eq!(write_block(&[7, 300], &mut out).unwrap(), 7);
+        assert_eq!(out, vec![0, 2, 4, 7, 249, 1, 44]);
+        out.push(9);
+        let mut r = &out[..];
+        assert_eq!(read_block(&mut r).unwrap(), vec![7, 300]);
+        assert_eq!(r, &[9][..]);
+
+        let kind = |data: &[u8]| read_block(data).unwrap_err().kind();
+        assert_eq!(kind(&[1, 0, 0]), io::ErrorKind::InvalidData);
+        assert_eq!(kind(&[0, 1, 2, 248, 1]), io::ErrorKind::InvalidData);
+        assert_eq!(kind(&[0, 1, 2, 1, 2]), io::ErrorKind::InvalidData);
+        assert_eq!(kind(&[0, 1, 2, 249, 1]), io::ErrorKind::InvalidData);
+        assert_eq!(kind(&[0, 1, 2, 1]), io::ErrorKind::UnexpectedEof);
+        assert_eq!(kind(&[0, 1]), io::ErrorKind::UnexpectedEof);
+        assert_eq!(kind(&[0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255]),
+                   io::ErrorKind::UnexpectedEof);
+    }
+
+    quickcheck! {
+        fn test_roundtrip(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut out = Vec::new();
+            let len = write_block(&values, &mut out).unwrap();
+            len == out.len() && read_block(&out[..]).unwrap() == values
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Sequential decoding from a byte slice.
+
+use super::{decode_count, DecodeError};
+
+/// A cursor into a byte slice, for decoding records of consecutive varu64s without threading
+/// the remaining slice through every call.
+///
+/// Errors carry the absolute offset into the slice at which the failing read started. A failed
+/// read leaves the position unchanged.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct VaruCursor<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> VaruCursor<'a> {
+    /// Create a cursor at the start of the `input`.
+    pub fn new(input: &'a [u8]) -> VaruCursor<'a> {
+        VaruCursor { input, position: 0 }
+    }
+
+    /// The offset of the cursor into the input.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The input after the cursor.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.input[self.position..]
+    }
+
+    /// Whether the cursor is at the end of the input.
+    pub fn is_empty(&self) -> bool {
+        self.position == self.input.len()
+    }
+
+    /// Decode the next varu64.
+    pub fn read_u64(&mut self) -> Result<u64, (DecodeError, usize)> {
+        match decode_count(self.remaining()) {
+            Ok((n, len)) => {
+                self.position += len;
+                Ok(n)
+            }
+            Err((e, _)) => Err((e, self.position)),
+        }
+    }
+
+    /// Skip the next varu64. Invalid encodings are not skipped, but reported as by `read_u64`.
+    pub fn skip(&mut self) -> Result<(), (DecodeError, usize)> {
+        self.read_u64().map(|_| ())
+    }
+
+    /// Read the next `len` bytes, failing with `UnexpectedEndOfInput` if there are fewer.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], (DecodeError, usize)> {
+        if self.input.len() - self.position < len {
+            let needed = len - (self.input.len() - self.position);
+            return Err((DecodeError::UnexpectedEndOfInput(needed), self.position));
+        }
+
+        let bytes = &self.input[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let mut cursor = VaruCursor::new(&[7, 249, 1, 0, 2, 8, 9, 248, 1, 250]);
+        assert_eq!(cursor.read_u64(), Ok(7));
+        cursor.skip().unwrap();
+        assert_eq!(cursor.position(), 4);
+        let len = cursor.read_u64().unwrap();
+        assert_eq!(cursor.read_bytes(len as usize), Ok(&[8, 9][..]));
+
+        assert_eq!(cursor.read_u64(), Err((NonCanonical(1), 7)));
+        assert_eq!(cursor.skip(), Err((NonCanonical(1), 7)));
+        assert_eq!(cursor.read_bytes(4), Err((UnexpectedEndOfInput(1), 7)));
+        assert_eq!(cursor.read_bytes(2), Ok(&[248, 1][..]));
+        assert_eq!(cursor.remaining(), &[250]);
+        assert_eq!(cursor.read_u64(), Err((UnexpectedEndOfInput(3), 9)));
+        assert_eq!(cursor.read_bytes(1), Ok(&[250][..]));
+        assert!(cursor.is_empty());
+        assert_eq!(cursor.read_u64(), Err((UnexpectedEndOfInput(1), 10)));
+    }
+}
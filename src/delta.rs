@@ -0,0 +1,96 @@
+//! Delta encoding of sorted sequences.
+//!
+//! A sorted sequence is encoded as the varu64 of its first value, followed by the varu64s of the
+//! differences between consecutive values. Sequences of close values, such as posting lists or
+//! increasing ids, have small differences and thus short encodings.
+
+use super::{decode, encode_append, DecodeError, EncodeError};
+
+/// Delta-encodes the `values`, appending them to `out` and returning how many bytes have been
+/// written.
+///
+/// # Errors
+/// If the `values` are not sorted in ascending order, an `Unsorted` error is returned and `out`
+/// is left unchanged. Equal values are allowed.
+pub fn encode_sorted(values: &[u64], out: &mut Vec<u8>) -> Result<usize, EncodeError> {
+    if let Some(i) = values.windows(2).position(|w| w[1] < w[0]) {
+        return Err(EncodeError::Unsorted(i + 1));
+    }
+
+    let mut written = 0;
+    let mut previous = 0;
+    for n in values.iter() {
+        written += encode_append(n - previous, out);
+        previous = *n;
+    }
+    Ok(written)
+}
+
+/// Decode all values of a sequence encoded by `encode_sorted` from the `input` buffer.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts. A difference that would take a value beyond
+/// `u64::MAX` results in a `ValueOutOfRange` error containing the difference.
+pub fn decode_sorted(input: &[u8]) -> Result<Vec<u64>, (DecodeError, usize)> {
+    let mut values = Vec::new();
+    let mut previous = 0u64;
+    let mut remaining = input;
+
+    while !remaining.is_empty() {
+        let offset = input.len() - remaining.len();
+        let (delta, tail) = decode(remaining).map_err(|(e, _)| (e, offset))?;
+        previous = previous
+            .checked_add(delta)
+            .ok_or((DecodeError::ValueOutOfRange(delta), offset))?;
+        values.push(previous);
+        remaining = tail;
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let mut out = vec![42];
+        assert_eq!(encode_sorted(&[300, 300, 307, 1000], &mut out), Ok(8));
+        assert_eq!(out, vec![42, 249, 1, 44, 0, 7, 249, 2, 181]);
+        assert_eq!(decode_sorted(&out[1..]), Ok(vec![300, 300, 307, 1000]));
+
+        assert_eq!(encode_sorted(&[1, 2, 1], &mut out), Err(EncodeError::Unsorted(2)));
+        assert_eq!(out.len(), 9);
+        assert_eq!(format!("{}", EncodeError::Unsorted(2)),
+                   "Values not sorted: the value at index 2 is too small");
+
+        assert_eq!(decode_sorted(&[7, 248, 1]), Err((NonCanonical(1), 1)));
+        assert_eq!(decode_sorted(&[255, 255, 255, 255, 255, 255, 255, 255, 255, 1]),
+                   Err((ValueOutOfRange(1), 9)));
+        assert_eq!(decode_sorted(&[]), Ok(vec![]));
+    }
+
+    quickcheck! {
+        fn test_roundtrip(values: Vec<u64>) -> bool {
+            let mut values = spread_all(&values);
+            let mut out = Vec::new();
+            match encode_sorted(&values, &mut out) {
+                Ok(_) => {}
+                Err(EncodeError::Unsorted(i)) => {
+                    if values[i] >= values[i - 1] {
+                        return false;
+                    }
+                    values.sort();
+                    encode_sorted(&values, &mut out).unwrap();
+                }
+                Err(_) => return false,
+            }
+
+            decode_sorted(&out) == Ok(values)
+        }
+    }
+}
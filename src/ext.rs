@@ -0,0 +1,93 @@
+//! Extension traits adding varu64 methods to all `std::io` readers and writers.
+//!
+//! Import the traits to call e.g. `r.read_varu64()` on any reader. IO errors are handled as
+//! described in the [crate docs](../index.html#io).
+
+use std::convert::TryFrom;
+use std::io;
+
+use super::biased::{unzigzag, zigzag};
+use super::reader::decode_read;
+use super::{encode_write, DecodeError};
+
+/// Methods for reading varu64s, implemented for all readers.
+pub trait ReadVaru64Ext: io::Read {
+    /// Read and decode a varu64, see `reader::decode_read`.
+    fn read_varu64(&mut self) -> io::Result<u64> {
+        decode_read(self)
+    }
+
+    /// Read and decode a varu64 as a `usize`. A number that does not fit results in an error of
+    /// kind `InvalidData`.
+    fn read_varu64_usize(&mut self) -> io::Result<usize> {
+        let n = self.read_varu64()?;
+        usize::try_from(n).map_err(|_| DecodeError::ValueOutOfRange(n).into())
+    }
+
+    /// Read and decode a signed number written by `WriteVaru64Ext::write_varu64_i64`.
+    fn read_varu64_i64(&mut self) -> io::Result<i64> {
+        self.read_varu64().map(unzigzag)
+    }
+}
+
+impl<R: io::Read + ?Sized> ReadVaru64Ext for R {}
+
+/// Methods for writing varu64s, implemented for all writers.
+pub trait WriteVaru64Ext: io::Write {
+    /// Encode `n` into the writer, returning how many bytes have been written.
+    fn write_varu64(&mut self, n: u64) -> io::Result<usize> {
+        encode_write(n, self)
+    }
+
+    /// Encode `n` into the writer, returning how many bytes have been written.
+    fn write_varu64_usize(&mut self, n: usize) -> io::Result<usize> {
+        self.write_varu64(n as u64)
+    }
+
+    /// Encode the signed `n` into the writer as by `biased::encode_i64`, returning how many
+    /// bytes have been written.
+    fn write_varu64_i64(&mut self, n: i64) -> io::Result<usize> {
+        self.write_varu64(zigzag(n))
+    }
+}
+
+impl<W: io::Write + ?Sized> WriteVaru64Ext for W {}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let mut out = Vec::new();
+        assert_eq!(out.write_varu64(300).unwrap(), 3);
+        assert_eq!(out.write_varu64_usize(7).unwrap(), 1);
+        assert_eq!(out.write_varu64_i64(-2).unwrap(), 1);
+        assert_eq!(out, vec![249, 1, 44, 7, 3]);
+
+        let mut r = &out[..];
+        assert_eq!(r.read_varu64().unwrap(), 300);
+        assert_eq!(r.read_varu64_usize().unwrap(), 7);
+        assert_eq!(r.read_varu64_i64().unwrap(), -2);
+        assert_eq!(r.read_varu64().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let dyn_reader: &mut dyn io::Read = &mut &[248, 1][..];
+        assert_eq!(dyn_reader.read_varu64().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    quickcheck! {
+        fn test_roundtrip(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut out = Vec::new();
+            for n in values.iter() {
+                out.write_varu64(*n).unwrap();
+            }
+
+            let mut r = &out[..];
+            values.iter().all(|n| r.read_varu64().unwrap() == *n) && r.is_empty()
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! An append-only record log for raw flash memory.
+//!
+//! Each record is stored as a varu64 holding its length, followed by the record itself. Records
+//! are packed into pages (the erase unit of the flash) and never cross a page boundary. If a
+//! record does not fit into the remaining space of a page, it is written to the start of the
+//! next page instead, leaving the rest of the page erased.
+//!
+//! Erased flash reads as `0xff`, which as a tag byte would indicate a record of at least
+//! 2^56 bytes. Since no record can be that large, an erased byte at the position of a length
+//! marks the end of the data in a page. This allows finding the end of the log after a reboot
+//! by scanning the pages, see `FlashLog::open`.
+//!
+//! The log does not detect records torn by a power loss during a write. Applications that need
+//! this should include a checksum in their records.
+
+use super::{encode, encoding_length, decode};
+
+/// The value of an erased byte.
+pub const ERASED: u8 = 0xff;
+
+/// A flash memory consisting of equally sized pages, the smallest units that can be erased.
+pub trait Flash {
+    /// The error type of the underlying storage.
+    type Error;
+
+    /// The size of a page in bytes.
+    fn page_size(&self) -> usize;
+
+    /// The number of pages.
+    fn page_count(&self) -> usize;
+
+    /// Fill `buf` with the data at `offset` bytes into the `page`.
+    fn read(&mut self, page: usize, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write `data` at `offset` bytes into the `page`. The log only writes to erased bytes.
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Erase the `page`, setting all of its bytes to `ERASED`.
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error>;
+}
+
+/// Everything that can go wrong when using a `FlashLog`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error<E> {
+    /// The underlying flash reported an error.
+    Flash(E),
+    /// The record does not fit into a single page. Contains the largest record size that fits.
+    RecordTooLarge(usize),
+    /// There is no space left for the record.
+    Full,
+    /// The buffer for reading a record is too small. Contains the size of the record.
+    BufferTooSmall(usize),
+    /// The page contains data that is not a valid record.
+    Corrupt(Position),
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Error<E> {
+        Error::Flash(e)
+    }
+}
+
+/// The position of a record in the log.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Position {
+    /// The page the record is stored in.
+    pub page: usize,
+    /// How many bytes into the page the record starts.
+    pub offset: usize,
+}
+
+/// An append-only log of records stored in a `Flash`.
+pub struct FlashLog<F: Flash> {
+    flash: F,
+    tail: Position, // Where the next record will be written.
+}
+
+impl<F: Flash> FlashLog<F> {
+    /// Erase all pages of the flash and create an empty log.
+    pub fn format(mut flash: F) -> Result<FlashLog<F>, Error<F::Error>> {
+        for page in 0..flash.page_count() {
+            flash.erase(page)?;
+        }
+
+        Ok(FlashLog {
+               flash,
+               tail: Position::default(),
+           })
+    }
+
+    /// Open a log that has previously been written to the flash, scanning the pages to find
+    /// the end of the log.
+    ///
+    /// Pages are filled in order, so the log ends in the last page whose first byte is not
+    /// erased. Only that page is scanned record by record.
+    pub fn open(mut flash: F) -> Result<FlashLog<F>, Error<F::Error>> {
+        let mut used = 0;
+        let mut first = [0u8; 1];
+        while used < flash.page_count() {
+            flash.read(used, 0, &mut first)?;
+            if first[0] == ERASED {
+                break;
+            }
+            used += 1;
+        }
+
+        let mut log = FlashLog {
+            flash,
+            tail: Position::default(),
+        };
+
+        if used > 0 {
+            let mut pos = Position {
+                page: used - 1,
+                offset: 0,
+            };
+            while let Some((_, len)) = log.read_header(pos)? {
+                pos.offset += len;
+            }
+            log.tail = pos;
+        }
+
+        Ok(log)
+    }
+
+    /// The position at which the next record will be written.
+    pub fn tail(&self) -> Position {
+        self.tail
+    }
+
+    /// The size of the largest record that can be appended.
+    pub fn max_record_size(&self) -> usize {
+        let page_size = self.flash.page_size();
+        let mut max = page_size.saturating_sub(1);
+        while max > 0 && encoding_length(max as u64) + max > page_size {
+            max -= 1;
+        }
+        max
+    }
+
+    /// Append a record to the log, returning its position.
+    pub fn append(&mut self, record: &[u8]) -> Result<Position, Error<F::Error>> {
+        let mut header = [0u8; 9];
+        let header_len = encode(record.len() as u64, &mut header[..]);
+        let page_size = self.flash.page_size();
+
+        if header_len + record.len() > page_size {
+            return Err(Error::RecordTooLarge(self.max_record_size()));
+        }
+
+        let mut pos = self.tail;
+        if pos.offset + header_len + record.len() > page_size {
+            pos = Position {
+                page: pos.page + 1,
+                offset: 0,
+            };
+        }
+        if pos.page >= self.flash.page_count() {
+            return Err(Error::Full);
+        }
+
+        self.flash.write(pos.page, pos.offset, &header[..header_len])?;
+        self.flash.write(pos.page, pos.offset + header_len, record)?;
+
+        self.tail = Position {
+            page: pos.page,
+            offset: pos.offset + header_len + record.len(),
+        };
+        Ok(pos)
+    }
+
+    /// Read the record at `pos` into `buf`, returning the length of the record and the
+    /// position of the next record. Returns `None` if there is no record at `pos`.
+    ///
+    /// Start at `Position::default()` to read the whole log.
+    pub fn read(&mut self,
+                mut pos: Position,
+                buf: &mut [u8])
+                -> Result<Option<(usize, Position)>, Error<F::Error>> {
+        loop {
+            if pos >= self.tail {
+                return Ok(None);
+            }
+
+            match self.read_header(pos)? {
+                None => {
+                    pos = Position {
+                        page: pos.page + 1,
+                        offset: 0,
+                    };
+                }
+                Some((header_len, len)) => {
+                    let record_len = len - header_len;
+                    if buf.len() < record_len {
+                        return Err(Error::BufferTooSmall(record_len));
+                    }
+
+                    self.flash
+                        .read(pos.page, pos.offset + header_len, &mut buf[..record_len])?;
+                    return Ok(Some((record_len,
+                                    Position {
+                                        page: pos.page,
+                                        offset: pos.offset + len,
+                                    })));
+                }
+            }
+        }
+    }
+
+    // Read the length of the record at `pos`, returning the length of its header and its total
+    // length, or `None` if there are no more records in the page.
+    fn read_header(&mut self, pos: Position) -> Result<Option<(usize, usize)>, Error<F::Error>> {
+        let page_size = self.flash.page_size();
+        if pos.offset >= page_size {
+            return Ok(None);
+        }
+
+        let mut header = [ERASED; 9];
+        let available = if page_size - pos.offset < 9 {
+            page_size - pos.offset
+        } else {
+            9
+        };
+        self.flash.read(pos.page, pos.offset, &mut header[..available])?;
+
+        if header[0] == ERASED {
+            return Ok(None);
+        }
+
+        match decode(&header[..available]) {
+            Ok((len, tail)) => {
+                let header_len = available - tail.len();
+                if (page_size - pos.offset - header_len) as u64 >= len {
+                    Ok(Some((header_len, header_len + len as usize)))
+                } else {
+                    Err(Error::Corrupt(pos))
+                }
+            }
+            Err(_) => Err(Error::Corrupt(pos)),
+        }
+    }
+
+    /// Get a reference to the underlying flash.
+    pub fn get_ref(&self) -> &F {
+        &self.flash
+    }
+
+    /// Return the underlying flash.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Flash simulated in memory, panicking when writing to non-erased bytes.
+    struct MemFlash {
+        page_size: usize,
+        data: Vec<u8>,
+    }
+
+    impl MemFlash {
+        fn new(page_size: usize, page_count: usize) -> MemFlash {
+            MemFlash {
+                page_size,
+                data: vec![0; page_size * page_count],
+            }
+        }
+    }
+
+    impl Flash for MemFlash {
+        type Error = ();
+
+        fn page_size(&self) -> usize {
+            self.page_size
+        }
+
+        fn page_count(&self) -> usize {
+            self.data.len() / self.page_size
+        }
+
+        fn read(&mut self, page: usize, offset: usize, buf: &mut [u8]) -> Result<(), ()> {
+            assert!(offset + buf.len() <= self.page_size);
+            let start = page * self.page_size + offset;
+            buf.copy_from_slice(&self.data[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), ()> {
+            assert!(offset + data.len() <= self.page_size);
+            let start = page * self.page_size + offset;
+            for (i, b) in data.iter().enumerate() {
+                assert_eq!(self.data[start + i], ERASED);
+                self.data[start + i] = *b;
+            }
+            Ok(())
+        }
+
+        fn erase(&mut self, page: usize) -> Result<(), ()> {
+            let start = page * self.page_size;
+            for b in self.data[start..start + self.page_size].iter_mut() {
+                *b = ERASED;
+            }
+            Ok(())
+        }
+    }
+
+    fn read_all(log: &mut FlashLog<MemFlash>) -> Vec<Vec<u8>> {
+        let mut records = Vec::new();
+        let mut buf = [0u8; 256];
+        let mut pos = Position::default();
+        while let Some((len, next)) = log.read(pos, &mut buf).unwrap() {
+            records.push(buf[..len].to_vec());
+            pos = next;
+        }
+        records
+    }
+
+    #[test]
+    fn fixtures() {
+        let mut log = FlashLog::format(MemFlash::new(8, 3)).unwrap();
+        assert_eq!(log.max_record_size(), 7);
+        assert_eq!(log.append(&[0; 8]), Err(Error::RecordTooLarge(7)));
+
+        assert_eq!(log.append(&[1, 2, 3]), Ok(Position { page: 0, offset: 0 }));
+        assert_eq!(log.append(&[]), Ok(Position { page: 0, offset: 4 }));
+        assert_eq!(log.append(&[4, 5]), Ok(Position { page: 0, offset: 5 }));
+        assert_eq!(log.append(&[7, 8, 9]), Ok(Position { page: 1, offset: 0 }));
+        assert_eq!(log.append(&[0; 7]), Ok(Position { page: 2, offset: 0 }));
+        assert_eq!(log.append(&[]), Err(Error::Full));
+        assert_eq!(log.tail(), Position { page: 2, offset: 8 });
+
+        let mut log = FlashLog::open(log.into_inner()).unwrap();
+        assert_eq!(log.tail(), Position { page: 2, offset: 8 });
+        assert_eq!(read_all(&mut log),
+                   vec![vec![1, 2, 3], vec![], vec![4, 5], vec![7, 8, 9], vec![0; 7]]);
+
+        let mut flash = MemFlash::new(8, 3);
+        flash.erase(1).unwrap();
+        flash.erase(2).unwrap();
+        flash.data[0] = 8;
+        assert_eq!(FlashLog::open(flash).err(),
+                   Some(Error::Corrupt(Position { page: 0, offset: 0 })));
+    }
+
+    quickcheck! {
+        fn test_reopen(records: Vec<Vec<u8>>, page_size: u8) -> bool {
+            let page_size = (page_size as usize) + 1;
+            let mut log = FlashLog::format(MemFlash::new(page_size, 16)).unwrap();
+
+            let mut appended = Vec::new();
+            for record in records.iter() {
+                match log.append(record) {
+                    Ok(_) => appended.push(record.clone()),
+                    Err(Error::RecordTooLarge(max)) => assert!(record.len() > max),
+                    Err(Error::Full) => break,
+                    Err(e) => panic!("{:?}", e),
+                }
+            }
+
+            let tail = log.tail();
+            let mut log = FlashLog::open(log.into_inner()).unwrap();
+            assert_eq!(log.tail(), tail);
+            assert_eq!(read_all(&mut log), appended);
+
+            true
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! A group varint format, packing four numbers behind a shared control byte.
+//!
+//! This is a distinct wire format, not part of the varu64 spec, for blocks of data whose layout
+//! the user controls. Decoding a group only needs the control byte to find all four numbers,
+//! which makes it considerably faster than decoding four varu64s.
+//!
+//! The control byte holds a two bit code for each number, the first number in the least
+//! significant bits. Code 0 stands for one byte, code 1 for two bytes and code 2 for four bytes,
+//! each in big-endian byteorder. Code 3 stands for a varu64 encoding. Numbers follow the control
+//! byte in order. Every number must use the smallest code that can represent it, a varu64 is
+//! only used for numbers of 2^32 or more, so every group has exactly one encoding.
+
+use super::{decode, encode_append, DecodeError};
+
+// The payload lengths of the fixed-width codes.
+const WIDTHS: [usize; 3] = [1, 2, 4];
+
+// The smallest code that can represent n.
+fn code(n: u64) -> u8 {
+    if n < 1 << 8 {
+        0
+    } else if n < 1 << 16 {
+        1
+    } else if n < 1 << 32 {
+        2
+    } else {
+        3
+    }
+}
+
+/// Encodes the four `values` as a group, appending it to `out` and returning how many bytes have
+/// been written.
+pub fn encode_group(values: &[u64; 4], out: &mut Vec<u8>) -> usize {
+    let start = out.len();
+    let mut control = 0;
+    for (i, n) in values.iter().enumerate() {
+        control |= code(*n) << (2 * i);
+    }
+    out.push(control);
+
+    for n in values.iter() {
+        match code(*n) {
+            3 => {
+                encode_append(*n, out);
+            }
+            c => out.extend_from_slice(&n.to_be_bytes()[8 - WIDTHS[c as usize]..]),
+        }
+    }
+    out.len() - start
+}
+
+/// Decode a group from the `input` buffer, returning the four numbers and the remaining bytes.
+///
+/// # Errors
+/// If a number does not use the smallest possible code, a `NonCanonical` error containing it is
+/// returned. Errors in a varu64 are reported as by `decode`. If there is not enough input data,
+/// an `UnexpectedEndOfInput` error is returned, with the number of bytes missing from the
+/// first incomplete number.
+pub fn decode_group(input: &[u8]) -> Result<([u64; 4], &[u8]), (DecodeError, &[u8])> {
+    let control = match input.first() {
+        Some(b) => *b,
+        None => return Err((DecodeError::UnexpectedEndOfInput(1), input)),
+    };
+
+    let mut values = [0u64; 4];
+    let mut remaining = &input[1..];
+    for (i, value) in values.iter_mut().enumerate() {
+        let c = (control >> (2 * i)) & 0b11;
+        if c == 3 {
+            let (n, tail) = decode(remaining)?;
+            *value = n;
+            remaining = tail;
+        } else {
+            let width = WIDTHS[c as usize];
+            if remaining.len() < width {
+                return Err((DecodeError::UnexpectedEndOfInput(width - remaining.len()),
+                            &remaining[remaining.len()..]));
+            }
+            let mut word = [0u8; 8];
+            word[8 - width..].copy_from_slice(&remaining[..width]);
+            *value = u64::from_be_bytes(word);
+            remaining = &remaining[width..];
+        }
+
+        if code(*value) != c {
+            return Err((DecodeError::NonCanonical(*value), remaining));
+        }
+    }
+
+    Ok((values, remaining))
+}
+
+/// Encodes the `values` as a varu64 holding their number, followed by groups of four values,
+/// the last one padded with zeros. Appends the encoding to `out` and returns how many bytes
+/// have been written.
+pub fn encode_groups(values: &[u64], out: &mut Vec<u8>) -> usize {
+    let mut written = encode_append(values.len() as u64, out);
+    for chunk in values.chunks(4) {
+        let mut group = [0u64; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        written += encode_group(&group, out);
+    }
+    written
+}
+
+/// Decode values encoded by `encode_groups` from the `input` buffer, returning them and the
+/// remaining bytes.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid group, and the offset into the
+/// `input` at which that group starts. Padding values other than zero result in a
+/// `NonCanonical` error containing the value.
+pub fn decode_groups(input: &[u8]) -> Result<(Vec<u64>, &[u8]), (DecodeError, usize)> {
+    let (count, mut remaining) = decode(input).map_err(|(e, _)| (e, 0))?;
+
+    // Every group takes up at least five bytes, so this does not trust the count for allocation.
+    let mut values = Vec::with_capacity(if count as usize > remaining.len() {
+                                            remaining.len()
+                                        } else {
+                                            count as usize
+                                        });
+    while (values.len() as u64) < count {
+        let offset = input.len() - remaining.len();
+        let (group, tail) = decode_group(remaining).map_err(|(e, _)| (e, offset))?;
+        let taken = if count - (values.len() as u64) < 4 {
+            (count - values.len() as u64) as usize
+        } else {
+            4
+        };
+        if let Some(n) = group[taken..].iter().find(|n| **n != 0) {
+            return Err((DecodeError::NonCanonical(*n), offset));
+        }
+        values.extend_from_slice(&group[..taken]);
+        remaining = tail;
+    }
+
+    Ok((values, remaining))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let mut out = Vec::new();
+        assert_eq!(encode_group(&[7, 300, 1 << 16, 1 << 32], &mut out), 14);
+        assert_eq!(out, vec![0b11_10_01_00, 7, 1, 44, 0, 1, 0, 0, 252, 1, 0, 0, 0, 0]);
+        out.push(9);
+        assert_eq!(decode_group(&out), Ok(([7, 300, 1 << 16, 1 << 32], &[9][..])));
+
+        assert_eq!(decode_group(&[0b01, 0, 7, 0, 0, 0]), Err((NonCanonical(7), &[0, 0, 0][..])));
+        assert_eq!(decode_group(&[0b11, 251, 0, 0, 0, 7, 0, 0, 0]),
+                   Err((NonCanonical(7), &[0, 0, 0][..])));
+        assert_eq!(decode_group(&[0b1000, 1, 0, 0]), Err((UnexpectedEndOfInput(2), &[][..])));
+        assert_eq!(decode_group(&[]), Err((UnexpectedEndOfInput(1), &[][..])));
+
+        out.clear();
+        assert_eq!(encode_groups(&[1, 2, 3, 4, 5], &mut out), 11);
+        assert_eq!(out, vec![5, 0, 1, 2, 3, 4, 0, 5, 0, 0, 0]);
+        assert_eq!(decode_groups(&out), Ok((vec![1, 2, 3, 4, 5], &[][..])));
+        out[8] = 6;
+        assert_eq!(decode_groups(&out), Err((NonCanonical(6), 6)));
+        assert_eq!(decode_groups(&out[..9]), Err((UnexpectedEndOfInput(1), 6)));
+    }
+
+    quickcheck! {
+        fn test_roundtrip(values: Vec<u64>, tail: Vec<u8>) -> bool {
+            let values = spread_all(&values);
+            let mut out = Vec::new();
+            let len = encode_groups(&values, &mut out);
+            out.extend_from_slice(&tail);
+
+            len == out.len() - tail.len() && decode_groups(&out) == Ok((values, &tail[..]))
+        }
+    }
+}
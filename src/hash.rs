@@ -0,0 +1,145 @@
+//! Hashing the values of encoded varu64s without collecting them first.
+
+use std::hash::Hasher;
+use std::io::{self, Read};
+
+use super::{decode, DecodeError};
+use super::source::{decode_from, ReadSource, SourceError};
+
+/// Feed every value of a buffer of concatenated varu64s into the hasher via `Hasher::write_u64`,
+/// returning how many values have been hashed.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts. The values before it have already been hashed.
+pub fn hash_values<H: Hasher>(input: &[u8],
+                              state: &mut H)
+                              -> Result<usize, (DecodeError, usize)> {
+    hash_slice(input, state, false)
+}
+
+/// Like `hash_values`, but noncanonical encodings are accepted and hashed like the canonical
+/// encodings of their values, so that padded inputs hash identically to their canonical forms.
+pub fn hash_values_canonicalized<H: Hasher>(input: &[u8],
+                                            state: &mut H)
+                                            -> Result<usize, (DecodeError, usize)> {
+    hash_slice(input, state, true)
+}
+
+fn hash_slice<H: Hasher>(input: &[u8],
+                         state: &mut H,
+                         canonicalize: bool)
+                         -> Result<usize, (DecodeError, usize)> {
+    let mut remaining = input;
+    let mut count = 0;
+
+    while !remaining.is_empty() {
+        let (n, tail) = match decode(remaining) {
+            Ok(ok) => ok,
+            Err((DecodeError::NonCanonical(n), tail)) if canonicalize => (n, tail),
+            Err((e, _)) => return Err((e, input.len() - remaining.len())),
+        };
+        state.write_u64(n);
+        count += 1;
+        remaining = tail;
+    }
+
+    Ok(count)
+}
+
+/// Feed every value read from `r` into the hasher via `Hasher::write_u64`, returning how many
+/// values have been hashed. The hasher ends up in the same state as with `hash_values` on all
+/// the data of the reader.
+///
+/// The reader is buffered internally. Interrupted and short reads are handled as described in
+/// the [crate docs](../index.html#io). Invalid encodings result in an error of kind
+/// `InvalidData`, an encoding that is cut off by the end of the reader in an error of kind
+/// `UnexpectedEof`.
+pub fn hash_values_read<R: Read, H: Hasher>(r: R, state: &mut H) -> io::Result<usize> {
+    hash_read(r, state, false)
+}
+
+/// Like `hash_values_read`, but noncanonical encodings are accepted and hashed like the
+/// canonical encodings of their values.
+pub fn hash_values_read_canonicalized<R: Read, H: Hasher>(r: R,
+                                                          state: &mut H)
+                                                          -> io::Result<usize> {
+    hash_read(r, state, true)
+}
+
+fn hash_read<R: Read, H: Hasher>(r: R, state: &mut H, canonicalize: bool) -> io::Result<usize> {
+    let mut s = ReadSource(io::BufReader::new(r));
+    let mut count = 0;
+
+    loop {
+        let n = match decode_from(&mut s) {
+            Ok(Some(n)) => n,
+            Ok(None) => return Ok(count),
+            Err(SourceError::Decode(DecodeError::NonCanonical(n))) if canonicalize => n,
+            Err(e) => return Err(e.into()),
+        };
+        state.write_u64(n);
+        count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::io;
+
+    use super::*;
+    use super::super::encode;
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_all;
+
+    fn expected(values: &[u64]) -> u64 {
+        let mut state = DefaultHasher::new();
+        for n in values.iter() {
+            state.write_u64(*n);
+        }
+        state.finish()
+    }
+
+    #[test]
+    fn fixtures() {
+        let padded = [7, 248, 3, 249, 1, 0];
+        let mut state = DefaultHasher::new();
+        assert_eq!(hash_values(&padded, &mut state), Err((NonCanonical(3), 1)));
+
+        let mut state = DefaultHasher::new();
+        assert_eq!(hash_values_canonicalized(&padded, &mut state), Ok(3));
+        assert_eq!(state.finish(), expected(&[7, 3, 256]));
+
+        let mut state = DefaultHasher::new();
+        assert_eq!(hash_values_read(&padded[..], &mut state).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+
+        let mut state = DefaultHasher::new();
+        assert_eq!(hash_values_read_canonicalized(&padded[..], &mut state).unwrap(), 3);
+        assert_eq!(state.finish(), expected(&[7, 3, 256]));
+
+        let mut state = DefaultHasher::new();
+        assert_eq!(hash_values_read(&[7, 249, 1][..], &mut state).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+    }
+
+    quickcheck! {
+        fn test_hash(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut data = Vec::new();
+            for n in values.iter() {
+                let mut buf = [0u8; 9];
+                let len = encode(*n, &mut buf[..]);
+                data.extend_from_slice(&buf[..len]);
+            }
+
+            let mut slice_state = DefaultHasher::new();
+            let mut read_state = DefaultHasher::new();
+            hash_values(&data, &mut slice_state).unwrap() == values.len() &&
+            hash_values_read(&data[..], &mut read_state).unwrap() == values.len() &&
+            slice_state.finish() == expected(&values) &&
+            read_state.finish() == expected(&values)
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Encoding and decoding of integer types smaller than `u64`.
+
+use std::convert::TryFrom;
+
+use super::{decode, encode, DecodeError};
+
+/// An unsigned integer type that can be encoded as a varu64.
+pub trait VarU64Int: Sized + Copy {
+    /// Encodes the number into the output buffer, returning how many bytes have been written.
+    ///
+    /// # Panics
+    /// Panics if the buffer is not large enough to hold the encoding.
+    fn encode(self, out: &mut [u8]) -> usize;
+
+    /// Decode a number from the `input` buffer, returning it and the remaining bytes.
+    ///
+    /// # Errors
+    /// Errors are the same as those of `decode`. Additionally, a valid encoding of a number that
+    /// does not fit into `Self` results in a `ValueOutOfRange` error, together with the bytes
+    /// after the encoding.
+    fn decode(input: &[u8]) -> Result<(Self, &[u8]), (DecodeError, &[u8])>;
+}
+
+macro_rules! impl_var_u64_int {
+    ($($t:ty),*) => {
+        $(
+            impl VarU64Int for $t {
+                fn encode(self, out: &mut [u8]) -> usize {
+                    encode(self as u64, out)
+                }
+
+                fn decode(input: &[u8]) -> Result<($t, &[u8]), (DecodeError, &[u8])> {
+                    let (n, tail) = decode(input)?;
+                    match <$t>::try_from(n) {
+                        Ok(n) => Ok((n, tail)),
+                        Err(_) => Err((DecodeError::ValueOutOfRange(n), tail)),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+impl_var_u64_int!(u8, u16, u32, u64, usize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_byte;
+
+    #[test]
+    fn fixtures() {
+        let mut buf = [0u8; 9];
+        assert_eq!(300u16.encode(&mut buf[..]), 3);
+        assert_eq!(u16::decode(&buf[..4]), Ok((300, &[0][..])));
+        assert_eq!(u8::decode(&buf[..4]), Err((ValueOutOfRange(300), &[0][..])));
+        assert_eq!(u8::decode(&[248, 255]), Ok((255, &[][..])));
+        assert_eq!(u32::decode(&[252, 1, 0, 0, 0, 0]), Err((ValueOutOfRange(1 << 32), &[][..])));
+        assert_eq!(u16::decode(&[248, 1]), Err((NonCanonical(1), &[][..])));
+    }
+
+    // Check that decoding the encoding `buf` of `n` as a `T` succeeds exactly if `n` fits.
+    fn check<T: VarU64Int + TryFrom<u64> + PartialEq>(n: u64, buf: &[u8]) -> bool {
+        match (T::decode(buf), T::try_from(n)) {
+            (Ok((m, tail)), Ok(expected)) => m == expected && tail.is_empty(),
+            (Err((ValueOutOfRange(m), tail)), Err(_)) => m == n && tail.is_empty(),
+            _ => false,
+        }
+    }
+
+    quickcheck! {
+        fn test_roundtrip(a: u8, b: u16, c: u32, d: usize) -> bool {
+            let (a, b, c) = (spread_byte(a), b.rotate_left(b as u32), c.rotate_left(c));
+            let d = d.rotate_left(d as u32);
+            let mut buf = [0u8; 36];
+            let mut len = a.encode(&mut buf[..]);
+            len += b.encode(&mut buf[len..]);
+            len += c.encode(&mut buf[len..]);
+            len += d.encode(&mut buf[len..]);
+
+            let (a2, tail) = u8::decode(&buf[..len]).unwrap();
+            let (b2, tail) = u16::decode(tail).unwrap();
+            let (c2, tail) = u32::decode(tail).unwrap();
+            let (d2, tail) = usize::decode(tail).unwrap();
+            (a2, b2, c2, d2) == (a, b, c, d) && tail.is_empty()
+        }
+
+        fn test_range(n: u64, shift: u8) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let mut buf = [0u8; 9];
+            let len = n.encode(&mut buf[..]);
+            let buf = &buf[..len];
+            check::<u8>(n, buf) && check::<u16>(n, buf) && check::<u32>(n, buf) &&
+            check::<usize>(n, buf)
+        }
+    }
+}
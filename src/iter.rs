@@ -0,0 +1,203 @@
+//! Iterators over buffers of concatenated varu64s.
+//!
+//! Unlike the validator, these are not built on `source::Source`: iterating from the back and
+//! reporting offsets into the input need the whole buffer at hand. Use `source::ValuesFrom` to
+//! iterate over the values of other inputs.
+
+use super::{decode, DecodeError};
+
+/// An iterator over the values of a buffer of concatenated varu64s, that can be iterated from
+/// both ends. Created by `iter_indexed`.
+///
+/// Since the format can only be parsed from front to back, creating the iterator scans the
+/// whole buffer once, remembering the length of each encoding (one byte per value).
+#[derive(Debug, Clone)]
+pub struct IndexedIter<'a> {
+    input: &'a [u8],
+    lens: Vec<u8>, // The length of each encoding.
+    front: usize, // Index into `lens` of the next value from the front.
+    back: usize, // Index into `lens` one past the next value from the back.
+    front_offset: usize, // Offset into `input` of the next value from the front.
+    back_offset: usize, // Offset into `input` one past the next value from the back.
+}
+
+/// Scan the `input`, returning an iterator over all values it contains.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts.
+pub fn iter_indexed(input: &[u8]) -> Result<IndexedIter<'_>, (DecodeError, usize)> {
+    let mut lens = Vec::new();
+    let mut remaining = input;
+
+    while !remaining.is_empty() {
+        match decode(remaining) {
+            Ok((_, tail)) => {
+                lens.push((remaining.len() - tail.len()) as u8);
+                remaining = tail;
+            }
+            Err((e, _)) => return Err((e, input.len() - remaining.len())),
+        }
+    }
+
+    Ok(IndexedIter {
+           input,
+           back: lens.len(),
+           lens,
+           front: 0,
+           front_offset: 0,
+           back_offset: input.len(),
+       })
+}
+
+impl<'a> IndexedIter<'a> {
+    // Decode the value at the given offset, which is known to be valid.
+    fn value_at(&self, offset: usize) -> u64 {
+        decode(&self.input[offset..]).unwrap().0
+    }
+}
+
+impl<'a> Iterator for IndexedIter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.front == self.back {
+            return None;
+        }
+
+        let n = self.value_at(self.front_offset);
+        self.front_offset += self.lens[self.front] as usize;
+        self.front += 1;
+        Some(n)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.back - self.front, Some(self.back - self.front))
+    }
+}
+
+impl<'a> DoubleEndedIterator for IndexedIter<'a> {
+    fn next_back(&mut self) -> Option<u64> {
+        if self.front == self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        self.back_offset -= self.lens[self.back] as usize;
+        Some(self.value_at(self.back_offset))
+    }
+}
+
+impl<'a> ExactSizeIterator for IndexedIter<'a> {}
+
+/// A lazy iterator over the values of a buffer of concatenated varu64s. Created by
+/// `iter_decode`.
+///
+/// After yielding an error, the iterator continues with the next encoding.
+#[derive(Debug, Clone)]
+pub struct DecodeIter<'a> {
+    input: &'a [u8],
+    offset: usize, // Offset into `input` of the next encoding.
+}
+
+/// Create an iterator decoding the values of the `input` one at a time.
+pub fn iter_decode(input: &[u8]) -> DecodeIter<'_> {
+    DecodeIter { input, offset: 0 }
+}
+
+impl<'a> DecodeIter<'a> {
+    /// The offset into the input of the next encoding, or the length of the input if all of it
+    /// has been decoded.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for DecodeIter<'a> {
+    type Item = Result<u64, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset == self.input.len() {
+            return None;
+        }
+
+        let (result, tail) = match decode(&self.input[self.offset..]) {
+            Ok((n, tail)) => (Ok(n), tail),
+            Err((e, tail)) => (Err(e), tail),
+        };
+        self.offset = self.input.len() - tail.len();
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.input.len() - self.offset;
+        (remaining.div_ceil(9), Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{encode, encode_append};
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let mut iter = iter_indexed(&[7, 248, 255, 249, 1, 0, 3]).unwrap();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next_back(), Some(3));
+        assert_eq!(iter.next(), Some(7));
+        assert_eq!(iter.next_back(), Some(256));
+        assert_eq!(iter.next(), Some(255));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        assert_eq!(iter_indexed(&[7, 248, 1]).unwrap_err(), (NonCanonical(1), 1));
+        assert_eq!(iter_indexed(&[7, 0, 249, 1]).unwrap_err(), (UnexpectedEndOfInput(1), 2));
+    }
+
+    #[test]
+    fn test_iter_decode() {
+        let mut iter = iter_decode(&[7, 248, 1, 249, 1, 0, 250, 1]);
+        assert_eq!(iter.offset(), 0);
+        assert_eq!(iter.next(), Some(Ok(7)));
+        assert_eq!(iter.offset(), 1);
+        assert_eq!(iter.next(), Some(Err(NonCanonical(1))));
+        assert_eq!(iter.offset(), 3);
+        assert_eq!(iter.next(), Some(Ok(256)));
+        assert_eq!(iter.next(), Some(Err(UnexpectedEndOfInput(2))));
+        assert_eq!(iter.offset(), 8);
+        assert_eq!(iter.next(), None);
+    }
+
+    quickcheck! {
+        fn test_rev(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut data = Vec::new();
+            for n in values.iter() {
+                let mut buf = [0u8; 9];
+                let len = encode(*n, &mut buf[..]);
+                data.extend_from_slice(&buf[..len]);
+            }
+
+            let forward: Vec<u64> = iter_indexed(&data).unwrap().collect();
+            let mut backward: Vec<u64> = iter_indexed(&data).unwrap().rev().collect();
+            backward.reverse();
+
+            forward == values && backward == values
+        }
+
+        fn test_iter_decode_valid(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut data = Vec::new();
+            for n in values.iter() {
+                encode_append(*n, &mut data);
+            }
+
+            let (lower, upper) = iter_decode(&data).size_hint();
+            let decoded: Vec<u64> = iter_decode(&data).map(|n| n.unwrap()).collect();
+            decoded == values && lower <= values.len() && upper == Some(data.len())
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Encodings for database keys, whose byte order matches the order of the encoded values.
+//!
+//! Canonical varu64 encodings already sort like the numbers they encode: a larger number never
+//! has a shorter encoding, a longer encoding has a larger tag byte, and encodings of the same
+//! length are big-endian. Since no encoding is a prefix of another one, this extends to
+//! composite keys: the concatenation of several encodings sorts like the tuple of the values.
+//! The `push_*` functions build such keys, the `decode*` functions take them apart again.
+//!
+//! Byte strings can not simply be length-prefixed, since that would order them by length first.
+//! `push_bytes` escapes them instead, see its documentation.
+
+use std::{error, fmt};
+use std::cmp::{min, Ordering};
+
+use super::{encode, encode_append, decode, decoded_len, DecodeError};
+
+/// Append the encoding of `n` to the `key`.
+pub fn push_u64(key: &mut Vec<u8>, n: u64) {
+    encode_append(n, key);
+}
+
+/// Compare the canonical encodings at the start of `a` and `b` without decoding them. This
+/// orders the same way as the encoded numbers, and ignores any bytes after the encodings.
+pub fn cmp_encoded(a: &[u8], b: &[u8]) -> Ordering {
+    let a = &a[..min(a.first().map_or(0, |tag| decoded_len(*tag)), a.len())];
+    let b = &b[..min(b.first().map_or(0, |tag| decoded_len(*tag)), b.len())];
+    a.cmp(b)
+}
+
+/// Encodes `n` into the output buffer with every byte complemented, returning how many bytes
+/// have been written. The encodings sort in descending numeric order, and are still
+/// self-delimiting, so they can be used in composite keys.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_descending(n: u64, out: &mut [u8]) -> usize {
+    let len = encode(n, out);
+    for b in out[..len].iter_mut() {
+        *b = !*b;
+    }
+    len
+}
+
+/// Decode a `u64` encoded by `encode_descending` from the `input` buffer, returning the number
+/// and the remaining bytes.
+///
+/// # Errors
+/// Errors are the same as those of `decode`.
+pub fn decode_descending(input: &[u8]) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
+    let mut tmp = [0u8; 9];
+    let len = match input.first() {
+        Some(tag) => min(decoded_len(!*tag), input.len()),
+        None => 0,
+    };
+    for (t, b) in tmp.iter_mut().zip(input[..len].iter()) {
+        *t = !*b;
+    }
+
+    match decode(&tmp[..len]) {
+        Ok((n, _)) => Ok((n, &input[len..])),
+        Err((e, _)) => Err((e, &input[len..])),
+    }
+}
+
+/// Append the encoding of `n` as produced by `encode_descending` to the `key`.
+pub fn push_u64_descending(key: &mut Vec<u8>, n: u64) {
+    let mut tmp = [0u8; 9];
+    let len = encode_descending(n, &mut tmp[..]);
+    key.extend_from_slice(&tmp[..len]);
+}
+
+// Map an i64 to a u64 such that the order is preserved, by flipping the sign bit.
+fn i64_to_u64(n: i64) -> u64 {
+    (n as u64) ^ (1 << 63)
+}
+
+fn u64_to_i64(n: u64) -> i64 {
+    (n ^ (1 << 63)) as i64
+}
+
+/// Encodes the signed integer `n` into the output buffer, returning how many bytes have been
+/// written. The encodings sort in numeric order, unlike zigzag encoding.
+///
+/// The sign bit is flipped, mapping `i64::MIN` to zero and `0` to 2^63, and the resulting `u64`
+/// is encoded as a varu64. Only values below `-2^63 + 2^56` get shorter encodings, every
+/// other value, including `0` and all small numbers, takes up 9 bytes. Use `biased::encode_i64`
+/// for compact encodings that do not need to sort. Encodings of different length still compare
+/// correctly, since the varu64 tag bytes order the length classes.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_i64(n: i64, out: &mut [u8]) -> usize {
+    encode(i64_to_u64(n), out)
+}
+
+/// Decode an `i64` encoded by `encode_i64` from the `input` buffer, returning the number and the
+/// remaining bytes.
+///
+/// # Errors
+/// Errors are the same as those of `decode`.
+pub fn decode_i64(input: &[u8]) -> Result<(i64, &[u8]), (DecodeError, &[u8])> {
+    decode(input).map(|(n, tail)| (u64_to_i64(n), tail))
+}
+
+/// Append the encoding of `n` as produced by `encode_i64` to the `key`.
+pub fn push_i64(key: &mut Vec<u8>, n: i64) {
+    push_u64(key, i64_to_u64(n));
+}
+
+// Map an f64 to a u64 such that the order is preserved: positive values get their sign bit
+// set, negative values have all bits flipped, reversing their order.
+fn f64_to_u64(n: f64) -> u64 {
+    let bits = n.to_bits();
+    if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) }
+}
+
+fn u64_to_f64(n: u64) -> f64 {
+    f64::from_bits(if n >> 63 == 1 { n ^ (1 << 63) } else { !n })
+}
+
+/// Encodes the float `n` into the output buffer, returning how many bytes have been written.
+/// The encodings sort in numeric order.
+///
+/// Unlike the comparison operators on `f64`, the encodings order `-0.0` before `0.0`. NaNs with
+/// the sign bit cleared sort after positive infinity, those with the sign bit set before
+/// negative infinity. Decoding returns the exact bits that were encoded.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_f64(n: f64, out: &mut [u8]) -> usize {
+    encode(f64_to_u64(n), out)
+}
+
+/// Decode an `f64` encoded by `encode_f64` from the `input` buffer, returning the number and the
+/// remaining bytes.
+///
+/// # Errors
+/// Errors are the same as those of `decode`.
+pub fn decode_f64(input: &[u8]) -> Result<(f64, &[u8]), (DecodeError, &[u8])> {
+    decode(input).map(|(n, tail)| (u64_to_f64(n), tail))
+}
+
+/// Append the encoding of `n` as produced by `encode_f64` to the `key`.
+pub fn push_f64(key: &mut Vec<u8>, n: f64) {
+    push_u64(key, f64_to_u64(n));
+}
+
+// Terminates an escaped byte string.
+const TERMINATOR: [u8; 2] = [0x00, 0x01];
+// Stands for a zero byte within an escaped byte string.
+const ESCAPED_ZERO: [u8; 2] = [0x00, 0xff];
+
+/// Append the `bytes` to the `key`, such that keys sort by the lexicographic order of the
+/// `bytes`, followed by the order of any components pushed afterwards.
+///
+/// Every zero byte is escaped as `0x00 0xff`, and the string is terminated by `0x00 0x01`. A
+/// string that is a proper prefix of another one thus sorts first, since the terminator compares
+/// less than any continuation.
+pub fn push_bytes(key: &mut Vec<u8>, bytes: &[u8]) {
+    for b in bytes.iter() {
+        if *b == 0 {
+            key.extend_from_slice(&ESCAPED_ZERO);
+        } else {
+            key.push(*b);
+        }
+    }
+    key.extend_from_slice(&TERMINATOR);
+}
+
+/// Everything that can go wrong when decoding a byte string pushed by `push_bytes`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DecodeBytesError {
+    /// A zero byte is followed by a byte other than `0x01` or `0xff`. Contains that byte.
+    InvalidEscape(u8),
+    /// The input ended before the terminator.
+    UnexpectedEndOfInput,
+}
+
+impl fmt::Display for DecodeBytesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        match self {
+            DecodeBytesError::InvalidEscape(b) => {
+                write!(f, "Invalid key bytes: invalid escape sequence 0x00 {:#04x}", b)
+            }
+            DecodeBytesError::UnexpectedEndOfInput => {
+                write!(f, "Invalid key bytes: missing terminator")
+            }
+        }
+    }
+}
+
+impl error::Error for DecodeBytesError {}
+
+/// Decode a byte string pushed by `push_bytes` from the `input` buffer, returning the unescaped
+/// bytes and the remaining input.
+///
+/// # Errors
+/// On error, this also returns the remaining input, starting after the erroneous byte.
+pub fn decode_bytes(input: &[u8]) -> Result<(Vec<u8>, &[u8]), (DecodeBytesError, &[u8])> {
+    let mut bytes = Vec::new();
+    let mut i = 0;
+
+    while i < input.len() {
+        if input[i] != 0 {
+            bytes.push(input[i]);
+            i += 1;
+            continue;
+        }
+
+        match input.get(i + 1) {
+            Some(&0x01) => return Ok((bytes, &input[i + 2..])),
+            Some(&0xff) => bytes.push(0),
+            Some(&b) => return Err((DecodeBytesError::InvalidEscape(b), &input[i + 2..])),
+            None => break,
+        }
+        i += 2;
+    }
+
+    Err((DecodeBytesError::UnexpectedEndOfInput, &input[input.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        let mut buf = [0u8; 9];
+        assert_eq!(encode_i64(i64::MIN, &mut buf[..]), 1);
+        assert_eq!(buf[0], 0);
+        assert_eq!(encode_i64(0, &mut buf[..]), 9);
+        assert_eq!(buf, [255, 128, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(decode_i64(&buf[..]).unwrap(), (0, &[][..]));
+        assert_eq!(encode_i64(i64::MIN + (1 << 56), &mut buf[..]), 9);
+        assert_eq!(encode_i64(i64::MIN + (1 << 56) - 1, &mut buf[..]), 8);
+
+        let mut key = Vec::new();
+        push_i64(&mut key, -1);
+        push_u64(&mut key, 300);
+        let (n, tail) = decode_i64(&key).unwrap();
+        assert_eq!(n, -1);
+        assert_eq!(decode(tail).unwrap(), (300, &[][..]));
+
+        assert_eq!(cmp_encoded(&[249, 1, 0, 0], &[249, 1, 0, 255]), Ordering::Equal);
+        assert_eq!(cmp_encoded(&[7], &[248, 248]), Ordering::Less);
+        assert_eq!(cmp_encoded(&[], &[0]), Ordering::Less);
+
+        assert_eq!(encode_descending(256, &mut buf[..]), 3);
+        assert_eq!(&buf[..3], &[6, 254, 255]);
+        assert_eq!(decode_descending(&[6, 254, 255, 1]), Ok((256, &[1][..])));
+        assert_eq!(decode_descending(&[6, 254]),
+                   Err((DecodeError::UnexpectedEndOfInput(1), &[][..])));
+        assert_eq!(decode_descending(&[7, 254]), Err((DecodeError::NonCanonical(1), &[][..])));
+
+        let floats = [f64::NEG_INFINITY, -1.5, -0.0, 0.0, 1e-300, 2.0, f64::INFINITY];
+        for pair in floats.windows(2) {
+            let mut key_a = Vec::new();
+            push_f64(&mut key_a, pair[0]);
+            let mut key_b = Vec::new();
+            push_f64(&mut key_b, pair[1]);
+            assert!(key_a < key_b);
+        }
+        assert_eq!(encode_f64(-0.0, &mut buf[..]), 9);
+        assert_eq!(decode_f64(&buf[..]).unwrap().0.to_bits(), (-0.0f64).to_bits());
+
+        let mut key = Vec::new();
+        push_bytes(&mut key, &[1, 0, 2]);
+        push_u64(&mut key, 7);
+        assert_eq!(key, vec![1, 0, 0xff, 2, 0, 1, 7]);
+        let (bytes, tail) = decode_bytes(&key).unwrap();
+        assert_eq!(bytes, vec![1, 0, 2]);
+        assert_eq!(tail, &[7][..]);
+
+        assert_eq!(decode_bytes(&[1, 0, 2, 3]).unwrap_err(),
+                   (DecodeBytesError::InvalidEscape(2), &[3][..]));
+        assert_eq!(decode_bytes(&[1, 0]).unwrap_err(),
+                   (DecodeBytesError::UnexpectedEndOfInput, &[][..]));
+        assert_eq!(decode_bytes(&[1, 0, 0xff]).unwrap_err(),
+                   (DecodeBytesError::UnexpectedEndOfInput, &[][..]));
+    }
+
+    quickcheck! {
+        fn test_order_i64(a: i64, b: i64, shift_a: u8, shift_b: u8) -> bool {
+            let a = a.rotate_left(shift_a as u32);
+            let b = b.rotate_left(shift_b as u32);
+            let mut key_a = Vec::new();
+            push_i64(&mut key_a, a);
+            let mut key_b = Vec::new();
+            push_i64(&mut key_b, b);
+
+            decode_i64(&key_a).unwrap().0 == a && key_a.cmp(&key_b) == a.cmp(&b)
+        }
+
+        fn test_order_f64(a: u64, b: u64, shift_a: u8, shift_b: u8) -> bool {
+            let a = f64::from_bits(a.rotate_left(shift_a as u32));
+            let b = f64::from_bits(b.rotate_left(shift_b as u32));
+            if a.is_nan() || b.is_nan() || a == b {
+                return true;
+            }
+
+            let mut key_a = Vec::new();
+            push_f64(&mut key_a, a);
+            let mut key_b = Vec::new();
+            push_f64(&mut key_b, b);
+
+            decode_f64(&key_a).unwrap().0.to_bits() == a.to_bits() &&
+            (key_a < key_b) == (a < b)
+        }
+
+        fn test_order_bytes(a: (u64, Vec<u8>, u64), b: (u64, Vec<u8>, u64)) -> bool {
+            fn key(t: &(u64, Vec<u8>, u64)) -> Vec<u8> {
+                let mut key = Vec::new();
+                push_u64(&mut key, t.0 % 3);
+                push_bytes(&mut key, &t.1);
+                push_u64(&mut key, t.2);
+                key
+            }
+            let (key_a, key_b) = (key(&a), key(&b));
+
+            let (bytes, tail) = decode_bytes(&key_a[1..]).unwrap();
+            bytes == a.1 && decode(tail).unwrap() == (a.2, &[][..]) &&
+            key_a.cmp(&key_b) == (a.0 % 3, &a.1, a.2).cmp(&(b.0 % 3, &b.1, b.2))
+        }
+
+        fn test_order_descending(a: u64, b: u64, shift_a: u8, shift_b: u8) -> bool {
+            let a = a.rotate_left(shift_a as u32);
+            let b = b.rotate_left(shift_b as u32);
+            let mut key_a = Vec::new();
+            push_u64_descending(&mut key_a, a);
+            push_u64(&mut key_a, 1);
+            let mut key_b = Vec::new();
+            push_u64_descending(&mut key_b, b);
+            push_u64(&mut key_b, 0);
+
+            let mut enc_a = Vec::new();
+            push_u64(&mut enc_a, a);
+            let mut enc_b = Vec::new();
+            push_u64(&mut enc_b, b);
+
+            decode_descending(&key_a).unwrap() == (a, &[1][..]) &&
+            key_a.cmp(&key_b) == (b, 1).cmp(&(a, 0)) &&
+            cmp_encoded(&enc_a, &enc_b) == a.cmp(&b)
+        }
+
+        fn test_order_composite(a: (u64, i64), b: (u64, i64)) -> bool {
+            let mut key_a = Vec::new();
+            push_u64(&mut key_a, a.0 << 20);
+            push_i64(&mut key_a, a.1);
+            let mut key_b = Vec::new();
+            push_u64(&mut key_b, b.0 << 20);
+            push_i64(&mut key_b, b.1);
+
+            key_a.cmp(&key_b) == (a.0 << 20, a.1).cmp(&(b.0 << 20, b.1))
+        }
+    }
+}
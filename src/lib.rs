@@ -1,34 +1,91 @@
 //! Implementation of the [varu64 format](https://github.com/AljoschaMeyer/varu64-rs) in rust.
+//!
+//! # IO
+//! All functions operating on `std::io` readers and writers follow the same policy: errors of
+//! kind `ErrorKind::Interrupted` are retried transparently, and short reads and writes are
+//! continued until the full encoding has been transferred. Any other error is returned
+//! immediately, in which case an unspecified part of the encoding may already have been
+//! transferred.
+
+#![cfg_attr(not(feature = "memmap2"), forbid(unsafe_code))]
+#![cfg_attr(feature = "memmap2", deny(unsafe_code))]
 
 #[cfg(test)]
 #[macro_use]
 extern crate quickcheck;
+#[cfg(feature = "allocator-api2")]
+extern crate allocator_api2;
+#[cfg(feature = "bumpalo")]
+extern crate bumpalo;
+#[cfg(feature = "memmap2")]
+extern crate memmap2;
+#[cfg(feature = "num-bigint")]
+extern crate num_bigint;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+#[cfg(feature = "ufmt")]
+extern crate ufmt;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_test;
 
-use std::{fmt, error, io};
+use std::{cmp, fmt, error, io, num, ops, slice, str};
+use std::convert::TryFrom;
 
 pub mod nb;
+#[cfg(feature = "allocator-api2")]
+pub mod allocator;
+#[cfg(feature = "bumpalo")]
+pub mod arena;
+#[cfg(feature = "num-bigint")]
+pub mod big;
+#[cfg(feature = "memmap2")]
+pub mod mmap;
+#[cfg(feature = "rayon")]
+pub mod par;
+#[cfg(any(test, feature = "reference"))]
+pub mod reference;
+#[cfg(feature = "serde")]
+pub mod seq;
+#[cfg(feature = "smallvec")]
+pub mod small;
+#[cfg(feature = "u128")]
+pub mod wide;
+pub mod batch;
+pub mod biased;
+pub mod block;
+pub mod codec;
+pub mod column;
+pub mod cursor;
+pub mod delta;
+pub mod ext;
+pub mod flash;
+pub mod group;
+pub mod hash;
+pub mod int;
+pub mod iter;
+pub mod key;
+pub mod mux;
+pub mod packed;
+pub mod reader;
+pub mod rle;
+pub mod simple8b;
+pub mod source;
+pub mod spec;
+pub mod text;
+pub mod timestamps;
+pub mod trailer;
+pub mod validate;
+pub mod writer;
 
 /// Return how many bytes the encoding of `n` will take up.
 pub fn encoding_length(n: u64) -> usize {
-    if n < 248 {
-        1
-    } else if n < 256 {
-        2
-    } else if n < 65536 {
-        3
-    } else if n < 16777216 {
-        4
-    } else if n < 4294967296 {
-        5
-    } else if n < 1099511627776 {
-        6
-    } else if n < 281474976710656 {
-        7
-    } else if n < 72057594037927936 {
-        8
-    } else {
-        9
-    }
+    // The number of bytes without leading zero bytes, counted only if n needs a payload at all.
+    let payload = 8 - n.leading_zeros() as usize / 8;
+    1 + payload * (n > spec::MAX_SINGLE_BYTE as u64) as usize
 }
 
 /// Encodes `n` into the output buffer, returning how many bytes have been written.
@@ -36,62 +93,210 @@ pub fn encoding_length(n: u64) -> usize {
 /// # Panics
 /// Panics if the buffer is not large enough to hold the encoding.
 pub fn encode(n: u64, out: &mut [u8]) -> usize {
-    if n < 248 {
+    let len = encoding_length(n);
+    if len == 1 {
         out[0] = n as u8;
-        1
-    } else if n < 256 {
-        out[0] = 248;
-        write_bytes(n, 1, &mut out[1..]);
-        2
-    } else if n < 65536 {
-        out[0] = 249;
-        write_bytes(n, 2, &mut out[1..]);
-        3
-    } else if n < 16777216 {
-        out[0] = 250;
-        write_bytes(n, 3, &mut out[1..]);
-        4
-    } else if n < 4294967296 {
-        out[0] = 251;
-        write_bytes(n, 4, &mut out[1..]);
-        5
-    } else if n < 1099511627776 {
-        out[0] = 252;
-        write_bytes(n, 5, &mut out[1..]);
-        6
-    } else if n < 281474976710656 {
-        out[0] = 253;
-        write_bytes(n, 6, &mut out[1..]);
-        7
-    } else if n < 72057594037927936 {
-        out[0] = 254;
-        write_bytes(n, 7, &mut out[1..]);
-        8
     } else {
-        out[0] = 255;
-        write_bytes(n, 8, &mut out[1..]);
-        9
+        out[0] = spec::TAGS[len - 2];
+        write_bytes(n, len - 1, &mut out[1..]);
+    }
+    len
+}
+
+/// Encodes `n` into the end of the output buffer, so that the encoding ends at its last byte,
+/// returning how many bytes have been written. For serializers that build messages from the
+/// back to the front.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_reverse(n: u64, out: &mut [u8]) -> usize {
+    let len = encoding_length(n);
+    assert!(out.len() >= len, "buffer too small for the encoding");
+
+    let start = out.len() - len;
+    encode(n, &mut out[start..])
+}
+
+/// Encodes `n` into a new vector.
+pub fn encode_to_vec(n: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(encoding_length(n));
+    encode_append(n, &mut out);
+    out
+}
+
+/// Encodes `n`, appending it to `out` and returning how many bytes have been written.
+pub fn encode_append(n: u64, out: &mut Vec<u8>) -> usize {
+    if n <= spec::MAX_SINGLE_BYTE as u64 {
+        out.push(n as u8);
+        return 1;
+    }
+
+    let mut tmp = [0u8; 9];
+    let len = encode(n, &mut tmp[..]);
+    out.extend_from_slice(&tmp[..len]);
+    len
+}
+
+/// Encodes `n` into the output buffer, returning how many bytes have been written, or an error
+/// if the buffer is too small. In that case, the buffer is left unchanged.
+pub fn try_encode(n: u64, out: &mut [u8]) -> Result<usize, EncodeError> {
+    let len = encoding_length(n);
+    if out.len() < len {
+        Err(EncodeError::BufferTooSmall(len))
+    } else {
+        Ok(encode(n, out))
     }
 }
 
 /// Encodes `n` into the writer, returning how many bytes have been written.
+///
+/// Interrupted and short writes are handled as described in the [crate docs](index.html#io).
 pub fn encode_write<W: io::Write>(n: u64, mut w: W) -> Result<usize, io::Error> {
+    if n <= spec::MAX_SINGLE_BYTE as u64 {
+        return w.write_all(&[n as u8]).map(|_| 1);
+    }
+
     let mut tmp = [0u8; 9];
     let written = encode(n, &mut tmp[..]);
     w.write_all(&tmp[..written]).map(|_| written)
 }
 
+/// Encodes `n` directly into the buffer of the cursor at its position, advancing the position
+/// and returning how many bytes have been written. Use `encode_append` for vectors.
+///
+/// Unlike `encode_write`, this does not go through `io::Write`, and writes nothing if the
+/// encoding does not fit. In that case, an error of kind `WriteZero` is returned.
+pub fn encode_into_cursor(n: u64, c: &mut io::Cursor<&mut [u8]>) -> io::Result<usize> {
+    let position = cmp::min(c.position(), c.get_ref().len() as u64) as usize;
+    let written = try_encode(n, &mut c.get_mut()[position..])
+        .map_err(|e| io::Error::new(io::ErrorKind::WriteZero, e))?;
+    c.set_position((position + written) as u64);
+    Ok(written)
+}
+
+/// Encodes `n` into exactly `len` bytes of the output buffer, padding the payload with leading
+/// zeros. Unless `len` is the canonical length, the result is a deliberately noncanonical
+/// encoding, for reserving a fixed-size slot that is patched in place later. Decode it with
+/// `decode_relaxed`.
+///
+/// # Errors
+/// If `n` needs more than `len` bytes, a `ValueOutOfRange` error is returned. A buffer shorter
+/// than `len` results in a `BufferTooSmall` error. In both cases, the buffer is left unchanged.
+///
+/// # Panics
+/// Panics if `len` is zero or greater than nine.
+pub fn encode_with_length(n: u64, len: usize, out: &mut [u8]) -> Result<usize, EncodeError> {
+    assert!((1..=spec::MAX_ENCODING_LENGTH).contains(&len),
+            "len must be between 1 and 9");
+
+    if len < encoding_length(n) {
+        return Err(EncodeError::ValueOutOfRange(n));
+    }
+    if out.len() < len {
+        return Err(EncodeError::BufferTooSmall(len));
+    }
+
+    if len == 1 {
+        out[0] = n as u8;
+    } else {
+        out[0] = spec::TAGS[len - 2];
+        write_bytes(n, len - 1, &mut out[1..]);
+    }
+    Ok(len)
+}
+
+/// Decode a `u64` from the `input` buffer like `decode`, but accept noncanonical encodings such
+/// as those produced by `encode_with_length`. Use `nb::Decoder::lenient` to do the same without
+/// blocking.
+///
+/// # Errors
+/// Returns `UnexpectedEndOfInput` with the remaining bytes if there is not enough input data.
+pub fn decode_relaxed(input: &[u8]) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
+    match decode(input) {
+        Err((NonCanonical(n), tail)) => Ok((n, tail)),
+        other => other,
+    }
+}
+
+/// Decode a `u64` from the `input` buffer like `decode`, but without checking that the encoding
+/// is canonical. Noncanonical encodings yield their value, as with `decode_relaxed`.
+///
+/// This is a fast path for trusted input, for example data written by `encode`. Only use it where
+/// accepting several encodings of the same number cannot cause harm, such as when the encoded
+/// bytes are not hashed or compared.
+///
+/// # Errors
+/// Returns `UnexpectedEndOfInput` with the remaining bytes if there is not enough input data.
+pub fn decode_unchecked(input: &[u8]) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
+    let first = match input.first() {
+        Some(b) => *b,
+        None => return Err((UnexpectedEndOfInput(1), input)),
+    };
+
+    let length = decoded_len(first);
+    if length == 1 {
+        return Ok((first as u64, &input[1..]));
+    }
+    if input.len() < length {
+        return Err((UnexpectedEndOfInput(length - input.len()), &input[input.len()..]));
+    }
+
+    Ok((read_payload(input, length), &input[length..]))
+}
+
+// Read the big-endian payload of the encoding of the given total length (at least two) at the
+// start of the input. If the input is long enough, this loads a full word after the tag and
+// shifts out the bytes after the payload, since copying a variable number of bytes is slow.
+// Otherwise, the payload is copied into the least significant bytes of a zero-padded word.
+fn read_payload(input: &[u8], length: usize) -> u64 {
+    let mut word = [0u8; 8];
+    if input.len() >= 9 {
+        word.copy_from_slice(&input[1..9]);
+        u64::from_be_bytes(word) >> (8 * (9 - length))
+    } else {
+        word[9 - length..].copy_from_slice(&input[1..length]);
+        u64::from_be_bytes(word)
+    }
+}
+
 // Write the k least significant bytes of n into out, in big-endian byteorder, panicking
 // if out is too small.
 //
-// k must be smaller than 8.
+// k must be at most 8.
 fn write_bytes(n: u64, k: usize, out: &mut [u8]) {
-    let bytes: [u8; 8] = unsafe { std::mem::transmute(u64::to_be(n)) };
-    for i in 0..k {
-        out[i] = bytes[(8 - k) + i];
+    out[..k].copy_from_slice(&n.to_be_bytes()[8 - k..]);
+}
+
+/// Return how many bytes an encoding starting with the `tag` byte takes up, including the tag.
+pub fn decoded_len(tag: u8) -> usize {
+    if tag <= spec::MAX_SINGLE_BYTE {
+        1
+    } else {
+        (tag - spec::MAX_SINGLE_BYTE) as usize + 1
     }
 }
 
+// Count the encodings in the `input` by skipping from tag byte to tag byte, without decoding or
+// validating them. A truncated encoding at the end is counted as well.
+fn count_encodings(input: &[u8]) -> usize {
+    let mut count = 0;
+    let mut offset = 0;
+    while offset < input.len() {
+        offset += decoded_len(input[offset]);
+        count += 1;
+    }
+    count
+}
+
+/// Return how many more bytes the encoding at the start of the `input` needs before it can be
+/// decoded, zero if the `input` already contains all of it. Returns `None` if the `input` is
+/// empty, since the length is not known before the tag byte.
+pub fn bytes_needed(input: &[u8]) -> Option<usize> {
+    input
+        .first()
+        .map(|tag| decoded_len(*tag).saturating_sub(input.len()))
+}
+
 /// Decode a `u64` from the `input` buffer, returning the number and the remaining bytes.
 ///
 /// # Errors
@@ -106,7 +311,7 @@ pub fn decode(input: &[u8]) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
     let first: u8;
     match input.get(0) {
         Some(b) => first = *b,
-        None => return Err((UnexpectedEndOfInput, input)),
+        None => return Err((UnexpectedEndOfInput(1), input)),
     }
 
     if (first | 0b0000_0111) == 0b1111_1111 {
@@ -115,17 +320,12 @@ pub fn decode(input: &[u8]) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
         // Total length of the encoded data is 1 byte for the tag plus the value of
         // the three least sgnificant bits incremented by 1.
         let length = (first & 0b0000_0111) as usize + 2;
-        let mut out: u64 = 0;
-
-        for i in 1..length {
-            out <<= 8;
-            match input.get(i) {
-                Some(b) => out += *b as u64,
-                None => return Err((UnexpectedEndOfInput, &input[i..])),
-            }
+        if input.len() < length {
+            return Err((UnexpectedEndOfInput(length - input.len()), &input[input.len()..]));
         }
 
-        if length > encoding_length(out) {
+        let out = read_payload(input, length);
+        if out < spec::THRESHOLDS[length - 2] {
             return Err((NonCanonical(out), &input[length..]));
         } else {
             return Ok((out, &input[length..]));
@@ -136,67 +336,1489 @@ pub fn decode(input: &[u8]) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
     }
 }
 
-/// Everything that can go wrong when decoding a varu64.
+/// Decode a `u64` from the `input` buffer like `decode`, but reject numbers larger than `max`.
+/// Use this for length prefixes, so that a hostile length can not trigger a huge allocation.
+///
+/// # Errors
+/// Returns an `ExceedsLimit` error with the bytes after the encoding if the number is larger than
+/// `max`, and errors as `decode` otherwise.
+pub fn decode_with_max(input: &[u8], max: u64) -> Result<(u64, &[u8]), (DecodeError, &[u8])> {
+    let (n, tail) = decode(input)?;
+    if n > max {
+        Err((ExceedsLimit {
+                 limit: max,
+                 actual: n,
+             },
+             tail))
+    } else {
+        Ok((n, tail))
+    }
+}
+
+/// Skip the encoding at the start of the `input`, returning the remaining bytes. This only looks
+/// at the tag byte, so it neither decodes the number nor checks its canonicity.
+///
+/// # Errors
+/// Returns `UnexpectedEndOfInput` if the `input` is shorter than the encoding.
+pub fn skip(input: &[u8]) -> Result<&[u8], DecodeError> {
+    match bytes_needed(input) {
+        Some(0) => Ok(&input[decoded_len(input[0])..]),
+        Some(needed) => Err(UnexpectedEndOfInput(needed)),
+        None => Err(UnexpectedEndOfInput(1)),
+    }
+}
+
+/// Decode a `usize` from the `input` buffer, returning the number and the remaining bytes. Use
+/// this for lengths and indices instead of casting a decoded `u64`, which silently truncates on
+/// 32 bit targets.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, plus `ValueOutOfRange` if the number exceeds
+/// `usize::MAX` on the current platform.
+pub fn decode_usize(input: &[u8]) -> Result<(usize, &[u8]), (DecodeError, &[u8])> {
+    int::VarU64Int::decode(input)
+}
+
+/// Decode a `u64` from an `input` buffer that must contain exactly one encoding.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, plus `TrailingBytes` if the `input` contains data
+/// after the encoding.
+pub fn decode_exact(input: &[u8]) -> Result<u64, DecodeError> {
+    match decode(input) {
+        Ok((n, tail)) => {
+            if tail.is_empty() {
+                Ok(n)
+            } else {
+                Err(TrailingBytes)
+            }
+        }
+        Err((e, _)) => Err(e),
+    }
+}
+
+/// Decode a `u64` from the `input` buffer, returning the number and how many bytes its encoding
+/// takes up.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, but report how many bytes were read instead of the
+/// remaining bytes.
+pub fn decode_count(input: &[u8]) -> Result<(u64, usize), (DecodeError, usize)> {
+    match decode(input) {
+        Ok((n, tail)) => Ok((n, input.len() - tail.len())),
+        Err((e, tail)) => Err((e, input.len() - tail.len())),
+    }
+}
+
+/// Decode a `u64` starting `offset` bytes into the `input` buffer, returning the number and the
+/// offset one past its encoding, where the next encoding would start.
+///
+/// # Errors
+/// Errors are the same as those of `decode`, but report the offset one past the data that was
+/// read instead of the remaining bytes.
+///
+/// # Panics
+/// Panics if `offset` is greater than the length of the `input`.
+pub fn decode_at(input: &[u8], offset: usize) -> Result<(u64, usize), (DecodeError, usize)> {
+    match decode_count(&input[offset..]) {
+        Ok((n, len)) => Ok((n, offset + len)),
+        Err((e, len)) => Err((e, offset + len)),
+    }
+}
+
+/// Encodes the `N` values one after the other into the output buffer, returning how many bytes
+/// have been written.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encodings.
+pub fn encode_n<const N: usize>(values: &[u64; N], out: &mut [u8]) -> usize {
+    encode_slice_into(&values[..], out)
+}
+
+/// Encodes the `values` one after the other, appending them to `out` and returning how many
+/// bytes have been written.
+pub fn encode_slice(values: &[u64], out: &mut Vec<u8>) -> usize {
+    let len: usize = values.iter().map(|n| encoding_length(*n)).sum();
+    let start = out.len();
+    out.resize(start + len, 0);
+    encode_slice_into(values, &mut out[start..])
+}
+
+/// Encodes the `values` one after the other into the output buffer, returning how many bytes
+/// have been written.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encodings.
+pub fn encode_slice_into(values: &[u64], out: &mut [u8]) -> usize {
+    let mut written = 0;
+    for n in values.iter() {
+        written += encode(*n, &mut out[written..]);
+    }
+    written
+}
+
+/// Decode exactly `N` consecutive values from the `input` buffer, returning them and the
+/// remaining bytes. Useful for fixed-arity headers, e.g.
+/// `let ([version, kind, len], payload) = decode_n::<3>(input)?;`.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts.
+pub fn decode_n<const N: usize>(input: &[u8]) -> Result<([u64; N], &[u8]), (DecodeError, usize)> {
+    let mut values = [0u64; N];
+    let mut remaining = input;
+
+    for value in values.iter_mut() {
+        match decode(remaining) {
+            Ok((n, tail)) => {
+                *value = n;
+                remaining = tail;
+            }
+            Err((e, _)) => return Err((e, input.len() - remaining.len())),
+        }
+    }
+
+    Ok((values, remaining))
+}
+
+/// A failed check of `self_test`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum DecodeError {
-    /// The encoding is not the shortest possible one for the number.
-    /// Contains the encoded number.
-    NonCanonical(u64),
-    /// The slice contains less data than the encoding needs.
-    UnexpectedEndOfInput,
+pub struct SelfTestFailure {
+    /// Which operation produced a wrong result, e.g. `"encode"` or `"nb::Decoder"`.
+    pub check: &'static str,
+    /// The number whose encoding or decoding failed.
+    pub n: u64,
 }
-use DecodeError::*;
 
-impl fmt::Display for DecodeError {
+impl fmt::Display for SelfTestFailure {
     fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
-        match self {
-            NonCanonical(n) => write!(f, "Invalid varu64: NonCanonical encoding of {}", n),
-            UnexpectedEndOfInput => write!(f, "Invalid varu64: Not enough input bytes"),
+        write!(f, "varu64 self test failed: {} of {}", self.check, self.n)
+    }
+}
+
+impl error::Error for SelfTestFailure {}
+
+// Numbers at the boundaries of the encoding lengths, and their encodings.
+static SELF_TEST_FIXTURES: [(u64, &[u8]); 18] =
+    [(0, &[0]),
+     (247, &[247]),
+     (248, &[248, 248]),
+     (255, &[248, 255]),
+     (256, &[249, 1, 0]),
+     (65535, &[249, 255, 255]),
+     (65536, &[250, 1, 0, 0]),
+     (16777215, &[250, 255, 255, 255]),
+     (16777216, &[251, 1, 0, 0, 0]),
+     (4294967295, &[251, 255, 255, 255, 255]),
+     (4294967296, &[252, 1, 0, 0, 0, 0]),
+     (1099511627775, &[252, 255, 255, 255, 255, 255]),
+     (1099511627776, &[253, 1, 0, 0, 0, 0, 0]),
+     (281474976710655, &[253, 255, 255, 255, 255, 255, 255]),
+     (281474976710656, &[254, 1, 0, 0, 0, 0, 0, 0]),
+     (72057594037927935, &[254, 255, 255, 255, 255, 255, 255, 255]),
+     (72057594037927936, &[255, 1, 0, 0, 0, 0, 0, 0, 0]),
+     (18446744073709551615, &[255, 255, 255, 255, 255, 255, 255, 255, 255])];
+
+/// Check that the compiled code behaves correctly on the current target, e.g. as part of a
+/// power-on self test or when initializing bindings.
+///
+/// This encodes and decodes the numbers at the boundaries of all encoding lengths with both the
+/// slice-based and the nonblocking API, and checks that noncanonical and incomplete encodings
+/// are rejected. Returns the first check that failed.
+pub fn self_test() -> Result<(), SelfTestFailure> {
+    for &(n, exp) in SELF_TEST_FIXTURES.iter() {
+        let fail = |check| Err(SelfTestFailure { check, n });
+
+        if encoding_length(n) != exp.len() {
+            return fail("encoding_length");
+        }
+
+        let mut buf = [0u8; 9];
+        if encode(n, &mut buf[..]) != exp.len() || &buf[..exp.len()] != exp {
+            return fail("encode");
+        }
+
+        let mut buf = [0u8; 9];
+        let mut enc = nb::Encoder::new(n);
+        let mut written = 0;
+        for i in 0..exp.len() {
+            written += enc.encode(&mut buf[i..i + 1]);
+        }
+        if written != exp.len() || &buf[..exp.len()] != exp {
+            return fail("nb::Encoder");
+        }
+
+        if decode(exp) != Ok((n, &[][..])) {
+            return fail("decode");
+        }
+
+        let mut dec = nb::Decoder::new();
+        for i in 0..exp.len() {
+            match (i + 1 == exp.len(), dec.decode(&exp[i..i + 1])) {
+                (false, (1, None)) => {}
+                (true, (1, Some(Ok(m)))) if m == n => {}
+                _ => return fail("nb::Decoder"),
+            }
+        }
+
+        if decode(&exp[..exp.len() - 1]).is_ok() {
+            return fail("decode of incomplete encoding");
+        }
+
+        if exp.len() < 9 {
+            let mut padded = [0u8; 9];
+            if exp.len() == 1 {
+                padded[0] = 248;
+                padded[1] = exp[0];
+            } else {
+                padded[0] = exp[0] + 1;
+                padded[2..exp.len() + 1].copy_from_slice(&exp[1..]);
+            }
+
+            if decode(&padded[..exp.len() + 1]) != Err((NonCanonical(n), &[][..])) {
+                return fail("decode of noncanonical encoding");
+            }
         }
     }
+
+    Ok(())
 }
 
-impl error::Error for DecodeError {}
+/// Decode all concatenated varu64s in the `input`.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts.
+pub fn decode_all(input: &[u8]) -> Result<Vec<u64>, (DecodeError, usize)> {
+    let mut values = Vec::new();
+    decode_all_into(input, &mut values)?;
+    Ok(values)
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Decode all concatenated varu64s in the `input`, appending them to `values` and returning how
+/// many were appended. Capacity for all of them is reserved up front, by scanning the tag bytes
+/// of the `input`.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts. The values before it have been appended to `values`.
+pub fn decode_all_into(input: &[u8], values: &mut Vec<u64>) -> Result<usize, (DecodeError, usize)> {
+    values.reserve(count_encodings(input));
 
-    // Assert that the given u64 encodes to the expected encoding, and that the
-    // expected encoding decodes to the u64.
-    fn test_fixture(n: u64, exp: &[u8]) {
-        let mut foo = [0u8; 9];
+    let start = values.len();
+    let mut remaining = input;
+    while !remaining.is_empty() {
+        match decode(remaining) {
+            Ok((n, tail)) => {
+                values.push(n);
+                remaining = tail;
+            }
+            Err((e, _)) => return Err((e, input.len() - remaining.len())),
+        }
+    }
 
-        let enc_len = encode(n, &mut foo[..]);
-        assert_eq!(&foo[..enc_len], exp);
+    Ok(values.len() - start)
+}
 
-        let (dec, tail) = decode(exp).unwrap();
-        assert_eq!(dec, n);
-        assert_eq!(tail, &[][..]);
+/// What to do when encountering an invalid encoding in `decode_all_with_policy`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RecoveryPolicy {
+    /// Stop decoding and return the error.
+    Stop,
+    /// Skip the invalid encoding.
+    SkipValue,
+    /// Replace the invalid encoding with the given value.
+    SubstituteWith(u64),
+}
+
+/// The result of `decode_all_with_policy`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Recovered {
+    /// The decoded values, including any substitutes.
+    pub values: Vec<u64>,
+    /// How many invalid encodings were skipped or substituted.
+    pub recovered: usize,
+}
+
+/// Decode all concatenated varu64s in the `input`, handling invalid encodings according to the
+/// `policy`.
+///
+/// A noncanonical encoding counts as a single invalid encoding, decoding continues after it. An
+/// encoding that is cut off by the end of the input also counts as a single invalid encoding.
+///
+/// # Errors
+/// Only returns an error for the `Stop` policy, the error of the first invalid encoding and the
+/// offset into the `input` at which that encoding starts.
+pub fn decode_all_with_policy(input: &[u8],
+                              policy: RecoveryPolicy)
+                              -> Result<Recovered, (DecodeError, usize)> {
+    let mut out = Recovered::default();
+    let mut remaining = input;
+
+    while !remaining.is_empty() {
+        match decode(remaining) {
+            Ok((n, tail)) => {
+                out.values.push(n);
+                remaining = tail;
+            }
+            Err((e, tail)) => {
+                match policy {
+                    RecoveryPolicy::Stop => return Err((e, input.len() - remaining.len())),
+                    RecoveryPolicy::SkipValue => {}
+                    RecoveryPolicy::SubstituteWith(n) => out.values.push(n),
+                }
+                out.recovered += 1;
+                remaining = tail;
+            }
+        }
     }
 
-    #[test]
-    fn fixtures() {
-        test_fixture(0, &[0]);
-        test_fixture(1, &[1]);
-        test_fixture(247, &[247]);
-        test_fixture(248, &[248, 248]);
-        test_fixture(255, &[248, 255]);
-        test_fixture(256, &[249, 1, 0]);
-        test_fixture(65535, &[249, 255, 255]);
-        test_fixture(65536, &[250, 1, 0, 0]);
-        test_fixture(72057594037927935, &[254, 255, 255, 255, 255, 255, 255, 255]);
-        test_fixture(72057594037927936, &[255, 1, 0, 0, 0, 0, 0, 0, 0]);
+    Ok(out)
+}
 
-        assert_eq!(decode(&[]).unwrap_err(), (UnexpectedEndOfInput, &[][..]));
-        assert_eq!(decode(&[248]).unwrap_err(), (UnexpectedEndOfInput, &[][..]));
-        assert_eq!(decode(&[255, 0, 1, 2, 3, 4, 5]).unwrap_err(),
-                   (UnexpectedEndOfInput, &[][..]));
-        assert_eq!(decode(&[255, 0, 1, 2, 3, 4, 5, 6]).unwrap_err(),
-                   (UnexpectedEndOfInput, &[][..]));
+/// Find the earliest offset into the `input` from which the remainder of the buffer consists of
+/// one or more valid varu64s, without any trailing bytes. Returns `None` if there is no such
+/// offset.
+///
+/// The format is not self-synchronizing, so this is only a heuristic for salvaging data after
+/// a corrupt region: the returned offset might be in the middle of an encoding whose suffix
+/// happens to be valid.
+pub fn resync(input: &[u8]) -> Option<usize> {
+    // For each offset, whether the remainder of the buffer consists of valid varu64s only.
+    let mut clean = vec![false; input.len() + 1];
+    clean[input.len()] = true;
 
-        assert_eq!(decode(&[248, 42]).unwrap_err(), (NonCanonical(42), &[][..]));
-        assert_eq!(decode(&[249, 0, 42]).unwrap_err(),
-                   (NonCanonical(42), &[][..]));
+    let mut earliest = None;
+    for i in (0..input.len()).rev() {
+        if let Ok((_, tail)) = decode(&input[i..]) {
+            clean[i] = clean[input.len() - tail.len()];
+            if clean[i] {
+                earliest = Some(i);
+            }
+        }
+    }
+    earliest
+}
+
+/// Find the earliest offset into the `input` from which at least `min_values` consecutive
+/// varu64s can be decoded, or from which the remainder of the buffer consists of one or more
+/// valid varu64s. Returns `None` if there is no such offset.
+///
+/// Every candidate offset is checked by decoding at most `min_values` varu64s from it, so this
+/// takes time proportional to the length of the `input` times `min_values`, and allocates
+/// nothing. See `resync` for caveats.
+pub fn resync_min(input: &[u8], min_values: usize) -> Option<usize> {
+    (0..input.len()).find(|i| resyncs_at(&input[*i..], min_values))
+}
+
+// Whether at least `min_values` consecutive varu64s, and at least one, can be decoded from the
+// start of the input, or whether the input consists of one or more valid varu64s. This decodes
+// at most `min_values` of them.
+fn resyncs_at(input: &[u8], min_values: usize) -> bool {
+    let mut remaining = input;
+    for count in 0..cmp::max(min_values, 1) {
+        if remaining.is_empty() {
+            return count > 0;
+        }
+        match decode(remaining) {
+            Ok((_, tail)) => remaining = tail,
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+/// An iterator over the values of a possibly corrupted buffer of concatenated varu64s, that
+/// skips past invalid data instead of stopping. Created by `RecoveringDecoder::new`.
+///
+/// After an invalid encoding, decoding resumes at the next offset found as by `resync_min`, and
+/// an `Issue` is yielded whose range covers the skipped bytes. If there is no such offset, the
+/// issue covers the remainder of the buffer and iteration ends. The same caveats as for `resync`
+/// apply. Like `resync_min`, this only looks ahead as far as needed to check each candidate
+/// offset.
+#[derive(Debug, Clone)]
+pub struct RecoveringDecoder<'a> {
+    input: &'a [u8],
+    offset: usize,
+    min_values: usize,
+}
+
+impl<'a> RecoveringDecoder<'a> {
+    /// Create an iterator over the `input`, resynchronizing at offsets from which at least
+    /// `min_values` valid varu64s can be decoded (or which lead to the end of the buffer).
+    pub fn new(input: &'a [u8], min_values: usize) -> RecoveringDecoder<'a> {
+        RecoveringDecoder {
+            input,
+            offset: 0,
+            min_values,
+        }
+    }
+
+    /// The offset into the input of the next byte to decode.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for RecoveringDecoder<'a> {
+    type Item = Result<u64, validate::Issue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.input.len() {
+            return None;
+        }
+
+        let error = match decode(&self.input[self.offset..]) {
+            Ok((n, tail)) => {
+                self.offset = self.input.len() - tail.len();
+                return Some(Ok(n));
+            }
+            Err((e, _)) => e,
+        };
+
+        let start = self.offset;
+        self.offset = resync_min(&self.input[start + 1..], self.min_values)
+            .map_or(self.input.len(), |i| start + 1 + i);
+
+        Some(Err(validate::Issue {
+                     error,
+                     range: start..self.offset,
+                 }))
+    }
+}
+
+/// A `u64` that converts to and from its varu64 encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VarU64(pub u64);
+
+impl From<u64> for VarU64 {
+    fn from(n: u64) -> VarU64 {
+        VarU64(n)
+    }
+}
+
+impl From<VarU64> for u64 {
+    fn from(n: VarU64) -> u64 {
+        n.0
+    }
+}
+
+/// The encoding, padded with zeros. Since the length of an encoding is determined by its first
+/// byte, the padding can be ignored when decoding.
+impl From<VarU64> for [u8; 9] {
+    fn from(n: VarU64) -> [u8; 9] {
+        let mut out = [0u8; 9];
+        encode(n.0, &mut out[..]);
+        out
+    }
+}
+
+/// Decode a slice that must contain exactly one encoding, erroring with `TrailingBytes`
+/// otherwise.
+impl<'a> TryFrom<&'a [u8]> for VarU64 {
+    type Error = DecodeError;
+
+    fn try_from(input: &'a [u8]) -> Result<VarU64, DecodeError> {
+        decode_exact(input).map(VarU64)
+    }
+}
+
+/// Displays the number in decimal.
+impl fmt::Display for VarU64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Parses a number in decimal, like `u64::from_str`.
+impl str::FromStr for VarU64 {
+    type Err = num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<VarU64, num::ParseIntError> {
+        s.parse().map(VarU64)
+    }
+}
+
+/// A slice that is known to contain exactly one valid, canonical encoding.
+///
+/// Comparison and hashing operate on the bytes. Since the byte order of canonical encodings
+/// matches the numeric order, this orders the same way as the encoded values.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EncodedVaru64<'a>(&'a [u8]);
+
+impl<'a> EncodedVaru64<'a> {
+    /// Split the encoding at the start of the `input` off, returning it and the remaining bytes.
+    ///
+    /// # Errors
+    /// Errors are the same as those of `decode`.
+    pub fn parse(input: &'a [u8])
+                 -> Result<(EncodedVaru64<'a>, &'a [u8]), (DecodeError, &'a [u8])> {
+        let (_, tail) = decode(input)?;
+        Ok((EncodedVaru64(&input[..input.len() - tail.len()]), tail))
+    }
+
+    /// The encoded value.
+    pub fn value(&self) -> u64 {
+        decode(self.0).unwrap().0
+    }
+
+    /// The bytes of the encoding.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// The length of the encoding, between 1 and 9.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Always `false`, since an encoding has at least one byte.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Validate a slice that must contain exactly one encoding, erroring with `TrailingBytes`
+/// otherwise.
+impl<'a> TryFrom<&'a [u8]> for EncodedVaru64<'a> {
+    type Error = DecodeError;
+
+    fn try_from(input: &'a [u8]) -> Result<EncodedVaru64<'a>, DecodeError> {
+        match EncodedVaru64::parse(input) {
+            Ok((encoded, tail)) => {
+                if tail.is_empty() {
+                    Ok(encoded)
+                } else {
+                    Err(TrailingBytes)
+                }
+            }
+            Err((e, _)) => Err(e),
+        }
+    }
+}
+
+impl<'a> AsRef<[u8]> for EncodedVaru64<'a> {
+    fn as_ref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl<'a> From<EncodedVaru64<'a>> for VarU64 {
+    fn from(encoded: EncodedVaru64<'a>) -> VarU64 {
+        VarU64(encoded.value())
+    }
+}
+
+/// An encoding stored inline, without allocating. Created by `encode_owned`.
+///
+/// Dereferences to the bytes of the encoding. Comparison and hashing operate on the bytes, which
+/// orders the same way as the encoded values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EncodedVaru64Buf {
+    bytes: [u8; 9], // The encoding, padded with zeros.
+    len: u8,
+}
+
+/// Encodes `n` into a new `EncodedVaru64Buf`.
+pub fn encode_owned(n: u64) -> EncodedVaru64Buf {
+    let mut bytes = [0u8; 9];
+    let len = encode(n, &mut bytes[..]);
+    EncodedVaru64Buf {
+        bytes,
+        len: len as u8,
+    }
+}
+
+impl EncodedVaru64Buf {
+    /// Borrow the encoding as an `EncodedVaru64`.
+    pub fn as_encoded(&self) -> EncodedVaru64<'_> {
+        EncodedVaru64(self)
+    }
+
+    /// The encoded value.
+    pub fn value(&self) -> u64 {
+        self.as_encoded().value()
+    }
+}
+
+impl ops::Deref for EncodedVaru64Buf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl AsRef<[u8]> for EncodedVaru64Buf {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl IntoIterator for EncodedVaru64Buf {
+    type Item = u8;
+    type IntoIter = std::iter::Take<std::array::IntoIter<u8, 9>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(self.bytes).take(self.len as usize)
+    }
+}
+
+impl<'a> IntoIterator for &'a EncodedVaru64Buf {
+    type Item = &'a u8;
+    type IntoIter = slice::Iter<'a, u8>;
+
+    fn into_iter(self) -> slice::Iter<'a, u8> {
+        self.iter()
+    }
+}
+
+impl<'a> From<EncodedVaru64<'a>> for EncodedVaru64Buf {
+    fn from(encoded: EncodedVaru64<'a>) -> EncodedVaru64Buf {
+        let mut bytes = [0u8; 9];
+        bytes[..encoded.len()].copy_from_slice(encoded.as_bytes());
+        EncodedVaru64Buf {
+            bytes,
+            len: encoded.len() as u8,
+        }
+    }
+}
+
+impl From<VarU64> for EncodedVaru64Buf {
+    fn from(n: VarU64) -> EncodedVaru64Buf {
+        encode_owned(n.0)
+    }
+}
+
+/// A wrapper around a buffer of concatenated varu64s whose `Display` and `Debug` output shows
+/// how the buffer is grouped into encodings, for use in log statements.
+///
+/// Each encoding is rendered as its tag byte in decimal, followed by the payload bytes in
+/// hexadecimal, e.g. `[7, 248·ff, 249·01·00]`. Noncanonical encodings are marked with
+/// `(noncanonical)`, an encoding cut off by the end of the buffer with `(truncated)`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DisplayEncoded<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for DisplayEncoded<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        let mut input = self.0;
+        write!(f, "[")?;
+
+        while let Some(tag) = input.first() {
+            if input.len() != self.0.len() {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", tag)?;
+
+            let (tail, note) = match decode(input) {
+                Ok((_, tail)) => (tail, ""),
+                Err((NonCanonical(_), tail)) => (tail, " (noncanonical)"),
+                Err((_, tail)) => (tail, " (truncated)"),
+            };
+            let group_len = input.len() - tail.len();
+
+            for b in input[1..group_len].iter() {
+                write!(f, "·{:02x}", b)?;
+            }
+            write!(f, "{}", note)?;
+
+            input = &input[group_len..];
+        }
+
+        write!(f, "]")
+    }
+}
+
+impl<'a> fmt::Debug for DisplayEncoded<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Everything that can go wrong when decoding a varu64.
+///
+/// Functions decoding a sequence of varu64s report the offset of the failing encoding alongside
+/// the error.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DecodeError {
+    /// The encoding is not the shortest possible one for the number.
+    /// Contains the encoded number.
+    NonCanonical(u64),
+    /// The slice contains less data than the encoding needs. Contains how many more bytes are
+    /// needed, which is one if the slice is empty, since the length of an encoding is only
+    /// known from its first byte.
+    UnexpectedEndOfInput(usize),
+    /// The slice contains more data than the encoding, but was required to contain a single
+    /// encoding only.
+    TrailingBytes,
+    /// The encoding is valid, but the number does not fit into the requested integer type.
+    /// Contains the encoded number.
+    ValueOutOfRange(u64),
+    /// The encoding is valid, but the number is larger than the caller allows.
+    ExceedsLimit { limit: u64, actual: u64 },
+}
+use DecodeError::*;
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        match self {
+            NonCanonical(n) => write!(f, "Invalid varu64: NonCanonical encoding of {}", n),
+            UnexpectedEndOfInput(n) => {
+                write!(f, "Invalid varu64: Not enough input bytes, {} more needed", n)
+            }
+            TrailingBytes => write!(f, "Invalid varu64: Trailing bytes after the encoding"),
+            ValueOutOfRange(n) => write!(f, "Invalid varu64: {} is out of range", n),
+            ExceedsLimit { limit, actual } => {
+                write!(f, "Invalid varu64: {} exceeds the limit of {}", actual, limit)
+            }
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// `UnexpectedEndOfInput` becomes an error of kind `UnexpectedEof`, all other errors become
+/// errors of kind `InvalidData`.
+impl From<DecodeError> for io::Error {
+    fn from(e: DecodeError) -> io::Error {
+        match e {
+            UnexpectedEndOfInput(_) => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            _ => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for DecodeError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<W>) -> Result<(), W::Error>
+        where W: ufmt::uWrite + ?Sized
+    {
+        match self {
+            NonCanonical(n) => {
+                f.write_str("Invalid varu64: NonCanonical encoding of ")?;
+                ufmt::uDisplay::fmt(n, f)
+            }
+            UnexpectedEndOfInput(n) => {
+                f.write_str("Invalid varu64: Not enough input bytes, ")?;
+                ufmt::uDisplay::fmt(n, f)?;
+                f.write_str(" more needed")
+            }
+            TrailingBytes => f.write_str("Invalid varu64: Trailing bytes after the encoding"),
+            ValueOutOfRange(n) => {
+                f.write_str("Invalid varu64: ")?;
+                ufmt::uDisplay::fmt(n, f)?;
+                f.write_str(" is out of range")
+            }
+            ExceedsLimit { limit, actual } => {
+                f.write_str("Invalid varu64: ")?;
+                ufmt::uDisplay::fmt(actual, f)?;
+                f.write_str(" exceeds the limit of ")?;
+                ufmt::uDisplay::fmt(limit, f)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDebug for DecodeError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<W>) -> Result<(), W::Error>
+        where W: ufmt::uWrite + ?Sized
+    {
+        match self {
+            NonCanonical(n) => f.debug_tuple("NonCanonical")?.field(n)?.finish(),
+            UnexpectedEndOfInput(n) => f.debug_tuple("UnexpectedEndOfInput")?.field(n)?.finish(),
+            TrailingBytes => f.write_str("TrailingBytes"),
+            ValueOutOfRange(n) => f.debug_tuple("ValueOutOfRange")?.field(n)?.finish(),
+            ExceedsLimit { limit, actual } => {
+                f.debug_struct("ExceedsLimit")?
+                    .field("limit", limit)?
+                    .field("actual", actual)?
+                    .finish()
+            }
+        }
+    }
+}
+
+/// Everything that can go wrong when encoding a varu64.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EncodeError {
+    /// The output buffer is too small. Contains how many bytes the encoding needs.
+    BufferTooSmall(usize),
+    /// The value can not be represented by the encoding. Contains the value.
+    ValueOutOfRange(u64),
+    /// The values are required to be sorted, but are not. Contains the index of the first value
+    /// that is smaller than its predecessor.
+    Unsorted(usize),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        match self {
+            EncodeError::BufferTooSmall(n) => {
+                write!(f, "Output buffer too small: the encoding needs {} bytes", n)
+            }
+            EncodeError::ValueOutOfRange(n) => write!(f, "Value out of range: {}", n),
+            EncodeError::Unsorted(i) => {
+                write!(f, "Values not sorted: the value at index {} is too small", i)
+            }
+        }
+    }
+}
+
+impl error::Error for EncodeError {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    // Spread a number generated by quickcheck, which is small, over all encoding lengths.
+    pub fn spread(n: u64) -> u64 {
+        n.rotate_left(n as u32)
+    }
+
+    pub fn spread_all(values: &[u64]) -> Vec<u64> {
+        values.iter().map(|n| spread(*n)).collect()
+    }
+
+    // Map a byte generated by quickcheck, which is small, such that about an eighth of the
+    // bytes are tags of multi-byte encodings, for testing decoders on arbitrary data.
+    pub fn spread_byte(b: u8) -> u8 {
+        if b.is_multiple_of(4) { 240 + b / 4 % 16 } else { b }
+    }
+
+    pub fn spread_bytes(bytes: &[u8]) -> Vec<u8> {
+        bytes.iter().map(|b| spread_byte(*b)).collect()
+    }
+
+    #[test]
+    fn test_spread_byte() {
+        let spread: Vec<u8> = (0..100).map(spread_byte).collect();
+        for tag in 248..=255 {
+            assert!(spread.contains(&tag));
+        }
+    }
+
+    #[test]
+    fn test_encoded_varu64() {
+        let (encoded, tail) = EncodedVaru64::parse(&[249, 1, 0, 7]).unwrap();
+        assert_eq!((encoded.value(), encoded.as_bytes(), encoded.len(), tail),
+                   (256, &[249, 1, 0][..], 3, &[7][..]));
+        assert_eq!(EncodedVaru64::parse(&[248, 1]).unwrap_err(), (NonCanonical(1), &[][..]));
+
+        assert_eq!(EncodedVaru64::try_from(&[249, 1, 0][..]), Ok(encoded));
+        assert_eq!(EncodedVaru64::try_from(&[249, 1, 0, 7][..]), Err(TrailingBytes));
+        assert_eq!(EncodedVaru64::try_from(&[249, 1][..]), Err(UnexpectedEndOfInput(1)));
+        assert_eq!(VarU64::from(encoded), VarU64(256));
+    }
+
+    #[test]
+    fn test_encode_owned() {
+        let encoded = encode_owned(256);
+        assert_eq!(&encoded[..], &[249, 1, 0]);
+        assert_eq!(encoded.len(), 3);
+        assert_eq!(encoded.value(), 256);
+        assert_eq!(encoded.into_iter().collect::<Vec<u8>>(), vec![249, 1, 0]);
+        assert_eq!((&encoded).into_iter().count(), 3);
+        assert_eq!(EncodedVaru64Buf::from(encoded.as_encoded()), encoded);
+        assert_eq!(EncodedVaru64Buf::from(VarU64(7)).as_ref(), &[7]);
+    }
+
+    quickcheck! {
+        fn test_encoded_varu64_order(a: u64, b: u64, shift_a: u8, shift_b: u8) -> bool {
+            let (a, b) = (a.rotate_left(shift_a as u32), b.rotate_left(shift_b as u32));
+            let (buf_a, buf_b): ([u8; 9], [u8; 9]) = (VarU64(a).into(), VarU64(b).into());
+            let (enc_a, _) = EncodedVaru64::parse(&buf_a).unwrap();
+            let (enc_b, _) = EncodedVaru64::parse(&buf_b).unwrap();
+
+            enc_a.value() == a && enc_a.cmp(&enc_b) == a.cmp(&b) &&
+            encode_owned(a).cmp(&encode_owned(b)) == a.cmp(&b)
+        }
+    }
+
+    #[test]
+    fn test_encode_reverse() {
+        let mut buf = [0u8; 6];
+        let mut front = buf.len();
+        front -= encode_reverse(300, &mut buf[..front]);
+        front -= encode_reverse(7, &mut buf[..front]);
+        assert_eq!(front, 2);
+        assert_eq!(buf, [0, 0, 7, 249, 1, 44]);
+    }
+
+    #[test]
+    fn test_encode_append() {
+        assert_eq!(encode_to_vec(256), vec![249, 1, 0]);
+
+        let mut out = vec![1];
+        assert_eq!(encode_append(7, &mut out), 1);
+        assert_eq!(encode_append(300, &mut out), 3);
+        assert_eq!(out, vec![1, 7, 249, 1, 44]);
+    }
+
+    #[test]
+    fn test_encode_into_cursor() {
+        let mut buf = [0u8; 5];
+        let mut c = io::Cursor::new(&mut buf[..]);
+        c.set_position(1);
+        assert_eq!(encode_into_cursor(300, &mut c).unwrap(), 3);
+        assert_eq!(encode_into_cursor(256, &mut c).unwrap_err().kind(),
+                   io::ErrorKind::WriteZero);
+        assert_eq!(encode_into_cursor(7, &mut c).unwrap(), 1);
+        assert_eq!(c.position(), 5);
+        c.set_position(9);
+        assert_eq!(encode_into_cursor(7, &mut c).unwrap_err().kind(), io::ErrorKind::WriteZero);
+        assert_eq!(buf, [0, 249, 1, 44, 7]);
+    }
+
+    #[test]
+    fn test_try_encode() {
+        let mut buf = [0u8; 3];
+        assert_eq!(try_encode(256, &mut buf[..]), Ok(3));
+        assert_eq!(buf, [249, 1, 0]);
+        assert_eq!(try_encode(65536, &mut buf[..]), Err(EncodeError::BufferTooSmall(4)));
+        assert_eq!(buf, [249, 1, 0]);
+        assert_eq!(try_encode(0, &mut []), Err(EncodeError::BufferTooSmall(1)));
+    }
+
+    #[test]
+    fn test_encode_with_length() {
+        let mut buf = [0u8; 9];
+        assert_eq!(encode_with_length(7, 9, &mut buf[..]), Ok(9));
+        assert_eq!(buf, [255, 0, 0, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(decode(&buf[..]), Err((NonCanonical(7), &[][..])));
+        assert_eq!(decode_relaxed(&buf[..]), Ok((7, &[][..])));
+
+        assert_eq!(encode_with_length(256, 3, &mut buf[..]), Ok(3));
+        assert_eq!(&buf[..3], &[249, 1, 0]);
+        assert_eq!(encode_with_length(7, 1, &mut buf[..]), Ok(1));
+        assert_eq!(buf[0], 7);
+
+        assert_eq!(encode_with_length(256, 2, &mut buf[..]),
+                   Err(EncodeError::ValueOutOfRange(256)));
+        assert_eq!(encode_with_length(0, 4, &mut buf[..3]), Err(EncodeError::BufferTooSmall(4)));
+        assert_eq!(decode_relaxed(&[249, 0]), Err((UnexpectedEndOfInput(1), &[][..])));
+    }
+
+    #[test]
+    fn test_decode_unchecked() {
+        assert_eq!(decode_unchecked(&[7, 1]), Ok((7, &[1][..])));
+        assert_eq!(decode_unchecked(&[249, 1, 44]), Ok((300, &[][..])));
+        assert_eq!(decode_unchecked(&[250, 0, 1, 44, 9]), Ok((300, &[9][..])));
+        assert_eq!(decode_unchecked(&[255, 0, 1]), Err((UnexpectedEndOfInput(6), &[][..])));
+        assert_eq!(decode_unchecked(&[]), Err((UnexpectedEndOfInput(1), &[][..])));
+
+        // Arbitrary data holding noncanonical encodings.
+        let bytes: Vec<u8> = (0..1000u32).map(|i| (i * 37 % 100) as u8).collect();
+        let input = spread_bytes(&bytes);
+        let mut noncanonical = 0;
+        for i in 0..input.len() {
+            if let Err((NonCanonical(n), tail)) = decode(&input[i..]) {
+                assert_eq!(decode_unchecked(&input[i..]), Ok((n, tail)));
+                noncanonical += 1;
+            }
+        }
+        assert!(noncanonical > 0);
+    }
+
+    #[test]
+    fn test_recovering_decoder_covers_input() {
+        // How many cases yielded a value after an issue.
+        static RECOVERIES: AtomicUsize = AtomicUsize::new(0);
+
+        fn prop(input: Vec<u8>, min_values: usize) -> bool {
+            let input = spread_bytes(&input);
+            let mut dec = RecoveringDecoder::new(&input, min_values % 4);
+            let mut offset = 0;
+            let mut issue_found = false;
+            while let Some(item) = dec.next() {
+                match item {
+                    Ok(_) => {
+                        if issue_found {
+                            RECOVERIES.fetch_add(1, Ordering::Relaxed);
+                            issue_found = false;
+                        }
+                    }
+                    Err(issue) => {
+                        if issue.range.start != offset || issue.range.is_empty() {
+                            return false;
+                        }
+                        issue_found = true;
+                    }
+                }
+                offset = dec.offset();
+            }
+            offset == input.len()
+        }
+
+        quickcheck::quickcheck(prop as fn(Vec<u8>, usize) -> bool);
+        assert!(RECOVERIES.load(Ordering::Relaxed) > 0);
+    }
+
+    quickcheck! {
+        fn test_decode_unchecked_agrees(input: Vec<u8>) -> bool {
+            let input = spread_bytes(&input);
+            (0..input.len()).all(|i| decode_unchecked(&input[i..]) == decode_relaxed(&input[i..]))
+        }
+
+        fn test_encode_with_length_roundtrip(n: u64, shift: u8, len: u8) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let len = (len as usize % 9) + 1;
+            let mut buf = [0u8; 9];
+            match encode_with_length(n, len, &mut buf[..]) {
+                Ok(written) => written == len && decode_relaxed(&buf[..]) == Ok((n, &buf[len..])),
+                Err(_) => len < encoding_length(n),
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip() {
+        assert_eq!(skip(&[7, 1]), Ok(&[1][..]));
+        assert_eq!(skip(&[248, 1, 2]), Ok(&[2][..]));
+        assert_eq!(skip(&[249, 1]), Err(UnexpectedEndOfInput(1)));
+        assert_eq!(skip(&[]), Err(UnexpectedEndOfInput(1)));
+    }
+
+    #[test]
+    fn test_decode_usize() {
+        assert_eq!(decode_usize(&[249, 1, 0, 7]), Ok((256, &[7][..])));
+        assert_eq!(decode_usize(&[248, 1]), Err((NonCanonical(1), &[][..])));
+
+        let mut buf = [0u8; 9];
+        let len = encode(usize::MAX as u64, &mut buf[..]);
+        assert_eq!(decode_usize(&buf[..len]), Ok((usize::MAX, &[][..])));
+        if (usize::MAX as u64) < u64::MAX {
+            let len = encode(usize::MAX as u64 + 1, &mut buf[..]);
+            assert_eq!(decode_usize(&buf[..len]),
+                       Err((ValueOutOfRange(usize::MAX as u64 + 1), &[][..])));
+        }
+    }
+
+    #[test]
+    fn test_decode_exact() {
+        assert_eq!(decode_exact(&[249, 1, 0]), Ok(256));
+        assert_eq!(decode_exact(&[249, 1, 0, 0]), Err(TrailingBytes));
+        assert_eq!(decode_exact(&[248, 1, 0]), Err(NonCanonical(1)));
+        assert_eq!(decode_exact(&[249, 1]), Err(UnexpectedEndOfInput(1)));
+        assert_eq!(decode_exact(&[]), Err(UnexpectedEndOfInput(1)));
+    }
+
+    #[test]
+    fn test_encode_slice() {
+        let mut out = vec![42];
+        assert_eq!(encode_slice(&[7, 300, 248], &mut out), 6);
+        assert_eq!(out, vec![42, 7, 249, 1, 44, 248, 248]);
+        assert_eq!(encode_slice(&[], &mut out), 0);
+        assert_eq!(out.len(), 7);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(encode_slice_into(&[1, 2, 3], &mut buf[..]), 3);
+        assert_eq!(buf, [1, 2, 3, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_encode_slice_into_too_small() {
+        encode_slice_into(&[1, 300], &mut [0u8; 3]);
+    }
+
+    #[test]
+    fn test_decode_all() {
+        assert_eq!(decode_all(&[]), Ok(vec![]));
+        assert_eq!(decode_all(&[7, 249, 1, 44, 0]), Ok(vec![7, 300, 0]));
+        assert_eq!(decode_all(&[7, 248, 1, 0]), Err((NonCanonical(1), 1)));
+        assert_eq!(decode_all(&[7, 0, 250, 1]), Err((UnexpectedEndOfInput(2), 2)));
+    }
+
+    #[test]
+    fn test_decode_all_into() {
+        let mut values = vec![42];
+        assert_eq!(decode_all_into(&[7, 249, 1, 44], &mut values), Ok(2));
+        assert_eq!(values, vec![42, 7, 300]);
+        assert!(values.capacity() >= 3);
+
+        values.clear();
+        assert_eq!(decode_all_into(&[1, 2, 248, 3, 4], &mut values), Err((NonCanonical(3), 2)));
+        assert_eq!(values, vec![1, 2]);
+        assert_eq!(decode_all_into(&[], &mut values), Ok(0));
+    }
+
+    #[test]
+    fn test_decode_with_max() {
+        assert_eq!(decode_with_max(&[249, 1, 44, 7], 300), Ok((300, &[7][..])));
+        assert_eq!(decode_with_max(&[249, 1, 44, 7], 299),
+                   Err((ExceedsLimit {
+                            limit: 299,
+                            actual: 300,
+                        },
+                        &[7][..])));
+        assert_eq!(decode_with_max(&[255, 16, 0, 0, 0, 0, 0, 0, 0], 1 << 32),
+                   Err((ExceedsLimit {
+                            limit: 1 << 32,
+                            actual: 1 << 60,
+                        },
+                        &[][..])));
+        assert_eq!(decode_with_max(&[248, 1], 0), Err((NonCanonical(1), &[][..])));
+        assert_eq!(format!("{}", decode_with_max(&[9], 8).unwrap_err().0),
+                   "Invalid varu64: 9 exceeds the limit of 8");
+    }
+
+    #[test]
+    fn test_decode_count() {
+        let input = [7, 249, 1, 0, 248, 1, 250, 1];
+        assert_eq!(decode_count(&input[1..]), Ok((256, 3)));
+        assert_eq!(decode_count(&input[4..]), Err((NonCanonical(1), 2)));
+        assert_eq!(decode_count(&input[6..]), Err((UnexpectedEndOfInput(2), 2)));
+        assert_eq!(decode_count(&[]), Err((UnexpectedEndOfInput(1), 0)));
+
+        assert_eq!(decode_at(&input, 0), Ok((7, 1)));
+        assert_eq!(decode_at(&input, 1), Ok((256, 4)));
+        assert_eq!(decode_at(&input, 4), Err((NonCanonical(1), 6)));
+        assert_eq!(decode_at(&input, 8), Err((UnexpectedEndOfInput(1), 8)));
+    }
+
+    #[test]
+    fn test_decoded_len() {
+        assert_eq!(decoded_len(0), 1);
+        assert_eq!(decoded_len(247), 1);
+        assert_eq!(decoded_len(248), 2);
+        assert_eq!(decoded_len(255), 9);
+
+        assert_eq!(bytes_needed(&[]), None);
+        assert_eq!(bytes_needed(&[7, 1]), Some(0));
+        assert_eq!(bytes_needed(&[250, 1]), Some(2));
+        assert_eq!(bytes_needed(&[249, 1, 0, 5]), Some(0));
+    }
+
+    quickcheck! {
+        fn test_decoded_len_matches(n: u64, shift: u8) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let mut buf = [0u8; 9];
+            let len = encode(n, &mut buf[..]);
+            decoded_len(buf[0]) == len && encoding_length(n) == len
+        }
+    }
+
+    #[test]
+    fn test_decode_n() {
+        let mut buf = [0u8; 27];
+        assert_eq!(encode_n(&[7, 300, 0], &mut buf[..]), 5);
+        assert_eq!(&buf[..5], &[7, 249, 1, 44, 0]);
+        assert_eq!(decode_n::<3>(&buf[..6]), Ok(([7, 300, 0], &[0][..])));
+        assert_eq!(decode_n::<0>(&buf[..2]), Ok(([], &buf[..2])));
+
+        assert_eq!(decode_n::<3>(&[7, 248, 1, 0]), Err((NonCanonical(1), 1)));
+        assert_eq!(decode_n::<3>(&[7, 8]), Err((UnexpectedEndOfInput(1), 2)));
+    }
+
+    // Assert that the given u64 encodes to the expected encoding, and that the
+    // expected encoding decodes to the u64.
+    fn test_fixture(n: u64, exp: &[u8]) {
+        let mut foo = [0u8; 9];
+
+        let enc_len = encode(n, &mut foo[..]);
+        assert_eq!(&foo[..enc_len], exp);
+
+        let (dec, tail) = decode(exp).unwrap();
+        assert_eq!(dec, n);
+        assert_eq!(tail, &[][..]);
+    }
+
+    // A ufmt writer collecting everything into a string.
+    #[cfg(feature = "ufmt")]
+    pub struct UString(pub String);
+
+    #[cfg(feature = "ufmt")]
+    impl ufmt::uWrite for UString {
+        type Error = ();
+
+        fn write_str(&mut self, s: &str) -> Result<(), ()> {
+            self.0.push_str(s);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "ufmt")]
+    #[test]
+    fn test_ufmt() {
+        for e in [NonCanonical(42),
+                  UnexpectedEndOfInput(3),
+                  TrailingBytes,
+                  ValueOutOfRange(300),
+                  ExceedsLimit {
+                      limit: 1,
+                      actual: 2,
+                  }]
+                .iter() {
+            let mut display = UString(String::new());
+            ufmt::uwrite!(display, "{}", e).unwrap();
+            assert_eq!(display.0, format!("{}", e));
+
+            let mut debug = UString(String::new());
+            ufmt::uwrite!(debug, "{:?}", e).unwrap();
+            assert_eq!(debug.0, format!("{:?}", e));
+        }
+    }
+
+    // A writer that accepts at most one byte per call, and fails with `Interrupted` on every
+    // other call.
+    pub struct FlakyWriter {
+        pub data: Vec<u8>,
+        interrupt: bool,
+    }
+
+    impl FlakyWriter {
+        pub fn new() -> FlakyWriter {
+            FlakyWriter {
+                data: Vec::new(),
+                interrupt: true,
+            }
+        }
+    }
+
+    impl io::Write for FlakyWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if !self.interrupt {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "flaky"));
+            }
+
+            match buf.first() {
+                Some(b) => {
+                    self.data.push(*b);
+                    Ok(1)
+                }
+                None => Ok(0),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // A reader that returns at most one byte per call, and fails with `Interrupted` on every
+    // other call.
+    pub struct FlakyReader<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl<'a> FlakyReader<'a> {
+        pub fn new(data: &'a [u8]) -> FlakyReader<'a> {
+            FlakyReader {
+                data,
+                interrupt: true,
+            }
+        }
+    }
+
+    impl<'a> io::Read for FlakyReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if !self.interrupt {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "flaky"));
+            }
+
+            match (self.data.split_first(), buf.first_mut()) {
+                (Some((b, tail)), Some(out)) => {
+                    *out = *b;
+                    self.data = tail;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    quickcheck! {
+        fn test_encode_write_flaky(n: u64, shift: u8) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let mut buf = [0u8; 9];
+            let len = encode(n, &mut buf[..]);
+
+            let mut w = FlakyWriter::new();
+            assert_eq!(encode_write(n, &mut w).unwrap(), len);
+            assert_eq!(&w.data[..], &buf[..len]);
+
+            true
+        }
+    }
+
+    #[test]
+    fn fixtures() {
+        test_fixture(0, &[0]);
+        test_fixture(1, &[1]);
+        test_fixture(247, &[247]);
+        test_fixture(248, &[248, 248]);
+        test_fixture(255, &[248, 255]);
+        test_fixture(256, &[249, 1, 0]);
+        test_fixture(65535, &[249, 255, 255]);
+        test_fixture(65536, &[250, 1, 0, 0]);
+        test_fixture(72057594037927935, &[254, 255, 255, 255, 255, 255, 255, 255]);
+        test_fixture(72057594037927936, &[255, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(decode(&[]).unwrap_err(), (UnexpectedEndOfInput(1), &[][..]));
+        assert_eq!(decode(&[248]).unwrap_err(), (UnexpectedEndOfInput(1), &[][..]));
+        assert_eq!(decode(&[255, 0, 1, 2, 3, 4, 5]).unwrap_err(),
+                   (UnexpectedEndOfInput(2), &[][..]));
+        assert_eq!(decode(&[255, 0, 1, 2, 3, 4, 5, 6]).unwrap_err(),
+                   (UnexpectedEndOfInput(1), &[][..]));
+
+        assert_eq!(decode(&[248, 42]).unwrap_err(), (NonCanonical(42), &[][..]));
+        assert_eq!(decode(&[249, 0, 42]).unwrap_err(),
+                   (NonCanonical(42), &[][..]));
+    }
+
+    #[test]
+    fn test_var_u64_conversions() {
+        assert_eq!(VarU64::try_from(&[249, 1, 0][..]), Ok(VarU64(256)));
+        assert_eq!(VarU64::try_from(&[249, 1, 0, 0][..]), Err(TrailingBytes));
+        assert_eq!(VarU64::try_from(&[249, 1][..]), Err(UnexpectedEndOfInput(1)));
+        assert_eq!(VarU64::try_from(&[248, 1][..]), Err(NonCanonical(1)));
+
+        assert_eq!(<[u8; 9]>::from(VarU64(256)), [249, 1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(u64::from(VarU64::from(42)), 42);
+
+        assert_eq!(VarU64(256).to_string(), "256");
+        assert_eq!("256".parse(), Ok(VarU64(256)));
+        assert!("-1".parse::<VarU64>().is_err());
+        assert!(VarU64(7) < VarU64(256));
+    }
+
+    quickcheck! {
+        fn test_var_u64_roundtrip(n: u64, shift: u8) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let padded: [u8; 9] = VarU64(n).into();
+            let len = encoding_length(n);
+            VarU64::try_from(&padded[..len]) == Ok(VarU64(n))
+        }
+    }
+
+    #[test]
+    fn test_display_encoded() {
+        assert_eq!(format!("{}", DisplayEncoded(&[])), "[]");
+        assert_eq!(format!("{}", DisplayEncoded(&[7, 248, 255, 249, 1, 0])),
+                   "[7, 248·ff, 249·01·00]");
+        assert_eq!(format!("{:?}", DisplayEncoded(&[248, 7, 0, 250, 1])),
+                   "[248·07 (noncanonical), 0, 250·01 (truncated)]");
+    }
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(self_test(), Ok(()));
+    }
+
+    #[test]
+    fn test_decode_all_with_policy() {
+        let data = [1, 248, 2, 3, 250, 1];
+
+        assert_eq!(decode_all_with_policy(&data, RecoveryPolicy::Stop),
+                   Err((NonCanonical(2), 1)));
+        assert_eq!(decode_all_with_policy(&data, RecoveryPolicy::SkipValue),
+                   Ok(Recovered {
+                          values: vec![1, 3],
+                          recovered: 2,
+                      }));
+        assert_eq!(decode_all_with_policy(&data, RecoveryPolicy::SubstituteWith(0)),
+                   Ok(Recovered {
+                          values: vec![1, 0, 3, 0],
+                          recovered: 2,
+                      }));
+        assert_eq!(decode_all_with_policy(&[1, 3], RecoveryPolicy::Stop),
+                   Ok(Recovered {
+                          values: vec![1, 3],
+                          recovered: 0,
+                      }));
+    }
+
+    #[test]
+    fn test_resync() {
+        assert_eq!(resync(&[]), None);
+        assert_eq!(resync(&[1, 2, 3]), Some(0));
+        assert_eq!(resync(&[248, 1, 2, 3]), Some(1));
+        assert_eq!(resync(&[249, 0, 248, 1]), Some(3));
+        assert_eq!(resync(&[249, 1, 248]), Some(0));
+        assert_eq!(resync(&[2, 249, 0, 248, 1]), Some(4));
+        assert_eq!(resync(&[5, 248]), None);
+
+        assert_eq!(resync_min(&[248, 1, 2, 3], 1), Some(1));
+        assert_eq!(resync_min(&[248, 1, 2, 248], 1), Some(1));
+        assert_eq!(resync_min(&[248, 1, 2, 248], 2), Some(1));
+        assert_eq!(resync_min(&[248, 1, 2, 248], 3), None);
+        assert_eq!(resync_min(&[248, 1, 2, 248, 3, 4, 5], 2), Some(1));
+        assert_eq!(resync_min(&[248, 1, 2, 248, 3, 4], 3), Some(4));
+        assert_eq!(resync_min(&[], 0), None);
+    }
+
+    #[test]
+    fn test_recovering_decoder() {
+        let input = [7, 248, 1, 2, 3, 249, 1, 44, 250, 0];
+        let mut dec = RecoveringDecoder::new(&input, 2);
+        assert_eq!(dec.next(), Some(Ok(7)));
+        assert_eq!(dec.next(),
+                   Some(Err(validate::Issue {
+                                error: NonCanonical(1),
+                                range: 1..2,
+                            })));
+        assert_eq!(dec.offset(), 2);
+        assert_eq!(dec.next(), Some(Ok(1)));
+        assert_eq!(dec.next(), Some(Ok(2)));
+        assert_eq!(dec.next(), Some(Ok(3)));
+        assert_eq!(dec.next(), Some(Ok(300)));
+        assert_eq!(dec.next(),
+                   Some(Err(validate::Issue {
+                                error: UnexpectedEndOfInput(2),
+                                range: 8..9,
+                            })));
+        assert_eq!(dec.next(), Some(Ok(0)));
+        assert_eq!(dec.next(), None);
+    }
+
+    quickcheck! {
+        fn test_encode_slice_roundtrip(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut out = Vec::new();
+            let len = encode_slice(&values, &mut out);
+            len == out.len() && decode_all(&out) == Ok(values)
+        }
+
+        fn test_recovering_decoder_valid(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut data = Vec::new();
+            for n in values.iter() {
+                encode_append(*n, &mut data);
+            }
+
+            RecoveringDecoder::new(&data, 1).map(|n| n.unwrap()).collect::<Vec<u64>>() == values
+        }
+
+        fn test_resync_valid(values: Vec<u64>, garbage: Vec<u8>) -> bool {
+            let values = spread_all(&values);
+            let mut data = garbage.clone();
+            for n in values.iter() {
+                let mut buf = [0u8; 9];
+                let len = encode(*n, &mut buf[..]);
+                data.extend_from_slice(&buf[..len]);
+            }
+
+            match resync(&data) {
+                Some(offset) => assert!(offset <= garbage.len()),
+                None => assert!(values.is_empty()),
+            }
+
+            true
+        }
+
+        // With more required values than there are bytes, only offsets leading to the end of
+        // the buffer qualify, like for `resync`.
+        fn test_resync_min_unbounded(input: Vec<u8>) -> bool {
+            let input = spread_bytes(&input);
+            resync_min(&input, input.len() + 1) == resync(&input)
+        }
     }
 }
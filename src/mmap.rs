@@ -0,0 +1,93 @@
+//! Scanning memory-mapped files of concatenated varu64s, available with the `memmap2` feature.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use super::DecodeError;
+use super::iter::{iter_decode, DecodeIter};
+
+/// A file of concatenated varu64s, mapped into memory.
+#[derive(Debug)]
+pub struct MappedValues {
+    map: Mmap,
+}
+
+impl MappedValues {
+    /// Map the file at `path` into memory.
+    ///
+    /// The file must not be modified while it is mapped, see `memmap2::Mmap::map`. This is the
+    /// only unsafe code of the crate.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MappedValues> {
+        let file = File::open(path)?;
+        #[allow(unsafe_code)]
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MappedValues { map })
+    }
+
+    /// Return the contents of the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Return an iterator over the values of the file and their offsets, decoding them lazily.
+    pub fn iter(&self) -> MappedIter<'_> {
+        MappedIter(iter_decode(&self.map))
+    }
+}
+
+/// An iterator over the values of a `MappedValues` and the offsets of their encodings. Created
+/// by `MappedValues::iter`.
+///
+/// Errors contain the offset of the invalid encoding. After yielding an error, the iterator
+/// continues with the next encoding, so an encoding cut off by the end of the file is reported
+/// as an `UnexpectedEndOfInput` error as the last item.
+#[derive(Debug, Clone)]
+pub struct MappedIter<'a>(DecodeIter<'a>);
+
+impl<'a> Iterator for MappedIter<'a> {
+    type Item = Result<(usize, u64), (DecodeError, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.0.offset();
+        self.0
+            .next()
+            .map(|result| result.map(|n| (offset, n)).map_err(|e| (e, offset)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let path = env::temp_dir().join(format!("varu64-mmap-test-{}", std::process::id()));
+        fs::write(&path, [7, 249, 1, 44, 248, 1, 250, 1]).unwrap();
+
+        let values = MappedValues::open(&path).unwrap();
+        assert_eq!(values.as_bytes().len(), 8);
+        assert_eq!(values.iter().collect::<Vec<_>>(),
+                   vec![Ok((0, 7)),
+                        Ok((1, 300)),
+                        Err((NonCanonical(1), 4)),
+                        Err((UnexpectedEndOfInput(2), 6))]);
+        drop(values);
+
+        fs::write(&path, [0u8; 0]).unwrap();
+        assert_eq!(MappedValues::open(&path).unwrap().iter().next(), None);
+        fs::remove_file(&path).unwrap();
+
+        assert!(MappedValues::open(&path).is_err());
+    }
+}
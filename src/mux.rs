@@ -0,0 +1,308 @@
+//! Multiplexing several logical streams of frames over a single byte stream.
+//!
+//! Each frame is encoded as a varu64 stream id, followed by a varu64 holding the length of the
+//! payload, followed by the payload itself.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+
+use super::encode;
+use super::source::{decode_from, ReadSource};
+
+// The default limits on the frames a `Demuxer` queues.
+const DEFAULT_QUEUED_FRAMES: usize = 1024;
+const DEFAULT_QUEUED_BYTES: usize = 1 << 20;
+
+/// Writes frames of several logical streams to an underlying writer.
+#[derive(Debug)]
+pub struct Muxer<W> {
+    w: W,
+}
+
+impl<W: Write> Muxer<W> {
+    /// Create a muxer writing to `w`.
+    pub fn new(w: W) -> Muxer<W> {
+        Muxer { w }
+    }
+
+    /// Write a single frame with the given `payload` to the stream `stream_id`, returning how
+    /// many bytes have been written in total.
+    ///
+    /// Interrupted and short writes are handled as described in the
+    /// [crate docs](../index.html#io).
+    pub fn write_frame(&mut self, stream_id: u64, payload: &[u8]) -> io::Result<usize> {
+        let mut header = [0u8; 18];
+        let mut header_len = encode(stream_id, &mut header[..]);
+        header_len += encode(payload.len() as u64, &mut header[header_len..]);
+
+        self.w.write_all(&header[..header_len])?;
+        self.w.write_all(payload)?;
+        Ok(header_len + payload.len())
+    }
+
+    /// Get a writer for the stream `stream_id`, which writes the data of each call to `write`
+    /// as a single frame.
+    pub fn stream(&mut self, stream_id: u64) -> StreamWriter<'_, W> {
+        StreamWriter {
+            mux: self,
+            stream_id,
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.w
+    }
+
+    /// Get a mutable reference to the underlying writer. Writing to it directly corrupts the
+    /// framing.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+
+/// A writer for a single logical stream of a `Muxer`. Created by `Muxer::stream`.
+#[derive(Debug)]
+pub struct StreamWriter<'a, W: 'a> {
+    mux: &'a mut Muxer<W>,
+    stream_id: u64,
+}
+
+impl<'a, W: Write> StreamWriter<'a, W> {
+    /// The id of the stream this writes to.
+    pub fn stream_id(&self) -> u64 {
+        self.stream_id
+    }
+}
+
+impl<'a, W: Write> Write for StreamWriter<'a, W> {
+    /// Write all of `buf` as a single frame. Empty buffers do not result in a frame.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.mux.write_frame(self.stream_id, buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.mux.w.flush()
+    }
+}
+
+/// Reads frames from an underlying reader, dispatching them to per-stream queues.
+///
+/// The frame headers are read one byte per call to `read`, so unbuffered readers such as files
+/// or sockets should be wrapped in a `BufReader`.
+#[derive(Debug)]
+pub struct Demuxer<R> {
+    r: R,
+    default_limit: u64,
+    limits: HashMap<u64, u64>, // Limits that differ from the default.
+    queues: HashMap<u64, VecDeque<Vec<u8>>>, // Frames read while looking for other streams.
+    max_queued_frames: usize,
+    max_queued_bytes: usize,
+    queued_frames: usize, // How many frames are in `queues` in total.
+    queued_bytes: usize, // The total size of the payloads in `queues`.
+}
+
+impl<R: Read> Demuxer<R> {
+    /// Create a demuxer reading from `r`, rejecting frames with payloads of more than
+    /// `default_limit` bytes unless configured otherwise via `set_limit`.
+    ///
+    /// By default, `recv` queues at most 1024 frames with at most 1 MiB of payload in total,
+    /// use `set_queue_limits` to change this.
+    pub fn new(r: R, default_limit: u64) -> Demuxer<R> {
+        Demuxer {
+            r,
+            default_limit,
+            limits: HashMap::new(),
+            queues: HashMap::new(),
+            max_queued_frames: DEFAULT_QUEUED_FRAMES,
+            max_queued_bytes: DEFAULT_QUEUED_BYTES,
+            queued_frames: 0,
+            queued_bytes: 0,
+        }
+    }
+
+    /// Set the maximum number of frames and the maximum total payload size that `recv` queues
+    /// over all streams.
+    pub fn set_queue_limits(&mut self, max_frames: usize, max_bytes: usize) {
+        self.max_queued_frames = max_frames;
+        self.max_queued_bytes = max_bytes;
+    }
+
+    /// Set the maximum payload size for frames of the stream `stream_id`.
+    pub fn set_limit(&mut self, stream_id: u64, limit: u64) {
+        self.limits.insert(stream_id, limit);
+    }
+
+    /// Read the next frame from the underlying reader, returning its stream id and payload.
+    /// This ignores frames that have been queued by `recv`. Returns `None` if the reader ends
+    /// before the first byte of a frame.
+    ///
+    /// # Errors
+    /// A frame exceeding the limit of its stream results in an error of kind `InvalidData`.
+    /// Its payload is skipped, so reading can continue with the next frame. Invalid varu64s
+    /// result in an error of kind `InvalidData`, a frame cut off by the end of the reader in an
+    /// error of kind `UnexpectedEof`.
+    pub fn read_frame(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+        let stream_id = match decode_from(&mut ReadSource(&mut self.r))? {
+            Some(stream_id) => stream_id,
+            None => return Ok(None),
+        };
+        let len = match decode_from(&mut ReadSource(&mut self.r))? {
+            Some(len) => len,
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+
+        let limit = *self.limits.get(&stream_id).unwrap_or(&self.default_limit);
+        if len > limit {
+            let skipped = io::copy(&mut (&mut self.r).take(len), &mut io::sink())?;
+            if skipped < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Frame of stream {} exceeds the limit of {} bytes",
+                                              stream_id,
+                                              limit)));
+        }
+
+        let mut payload = Vec::new();
+        (&mut self.r).take(len).read_to_end(&mut payload)?;
+        if (payload.len() as u64) < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Some((stream_id, payload)))
+    }
+
+    /// Return the next frame of the stream `stream_id`. Frames of other streams that are read
+    /// while looking for it are queued, and returned by later calls for their streams. Returns
+    /// `None` if the reader ends before a frame of the stream is found.
+    ///
+    /// # Errors
+    /// Errors are the same as those of `read_frame`. A frame that would exceed the limits set
+    /// by `set_queue_limits` results in an error of kind `InvalidData`, and is dropped.
+    pub fn recv(&mut self, stream_id: u64) -> io::Result<Option<Vec<u8>>> {
+        if let Some(payload) = self.queues.get_mut(&stream_id).and_then(|q| q.pop_front()) {
+            self.queued_frames -= 1;
+            self.queued_bytes -= payload.len();
+            return Ok(Some(payload));
+        }
+
+        while let Some((id, payload)) = self.read_frame()? {
+            if id == stream_id {
+                return Ok(Some(payload));
+            }
+
+            if self.queued_frames >= self.max_queued_frames ||
+               self.queued_bytes + payload.len() > self.max_queued_bytes {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("Frame of stream {} exceeds the queue limits",
+                                                  id)));
+            }
+            self.queued_frames += 1;
+            self.queued_bytes += payload.len();
+            self.queues.entry(id).or_default().push_back(payload);
+        }
+        Ok(None)
+    }
+
+    /// How many frames of the stream `stream_id` are queued.
+    pub fn queued(&self, stream_id: u64) -> usize {
+        self.queues.get(&stream_id).map_or(0, |q| q.len())
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.r
+    }
+
+    /// Return the underlying reader. Queued frames are lost.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        let mut mux = Muxer::new(Vec::new());
+        assert_eq!(mux.write_frame(1, &[7, 8]).unwrap(), 4);
+        mux.stream(300).write_all(&[9]).unwrap();
+        mux.stream(1).write_all(&[]).unwrap();
+        mux.write_frame(2, &[0; 5]).unwrap();
+        mux.write_frame(1, &[]).unwrap();
+        let data = mux.into_inner();
+        assert_eq!(&data[..8], &[1, 2, 7, 8, 249, 1, 44, 1]);
+
+        let mut demux = Demuxer::new(&data[..], 4);
+        demux.set_limit(2, 5);
+        assert_eq!(demux.recv(300).unwrap(), Some(vec![9]));
+        assert_eq!(demux.queued(1), 1);
+        assert_eq!(demux.recv(1).unwrap(), Some(vec![7, 8]));
+        assert_eq!(demux.recv(1).unwrap(), Some(vec![]));
+        assert_eq!(demux.recv(2).unwrap(), Some(vec![0; 5]));
+        assert_eq!(demux.recv(2).unwrap(), None);
+
+        let mut demux = Demuxer::new(&data[..], 4);
+        demux.read_frame().unwrap();
+        demux.read_frame().unwrap();
+        assert_eq!(demux.read_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(demux.read_frame().unwrap(), Some((1, vec![])));
+        assert_eq!(demux.read_frame().unwrap(), None);
+
+        let mut demux = Demuxer::new(&data[..], 5);
+        demux.set_queue_limits(2, 5);
+        assert_eq!(demux.recv(7).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(demux.queued(1), 1);
+        assert_eq!(demux.queued(300), 1);
+        assert_eq!(demux.recv(300).unwrap(), Some(vec![9]));
+        assert_eq!(demux.recv(2).unwrap(), None);
+
+        let mut demux = Demuxer::new(&data[..], 5);
+        demux.set_queue_limits(3, 4);
+        assert_eq!(demux.recv(7).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(demux.recv(1).unwrap(), Some(vec![7, 8]));
+        assert_eq!(demux.recv(1).unwrap(), Some(vec![]));
+
+        // Lowering the limits below what is already queued.
+        let mut demux = Demuxer::new(&data[..], 5);
+        assert_eq!(demux.recv(300).unwrap(), Some(vec![9]));
+        demux.set_queue_limits(10, 1);
+        assert_eq!(demux.recv(7).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(demux.queued(1), 1);
+        assert_eq!(demux.recv(1).unwrap(), Some(vec![7, 8]));
+
+        let mut demux = Demuxer::new(&data[..3], 4);
+        assert_eq!(demux.read_frame().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    quickcheck! {
+        fn test_roundtrip(frames: Vec<(u64, Vec<u8>)>) -> bool {
+            let mut mux = Muxer::new(Vec::new());
+            for &(id, ref payload) in frames.iter() {
+                mux.write_frame(id % 4, payload).unwrap();
+            }
+            let data = mux.into_inner();
+
+            let mut demux = Demuxer::new(&data[..], u64::MAX);
+            demux.set_queue_limits(usize::MAX, usize::MAX);
+            for id in 0..4 {
+                for (_, payload) in frames.iter().filter(|f| f.0 % 4 == id) {
+                    assert_eq!(demux.recv(id).unwrap().as_ref(), Some(payload));
+                }
+            }
+            demux.recv(0).unwrap().is_none()
+        }
+    }
+}
@@ -2,7 +2,11 @@
 
 use std::cmp::min;
 
-use super::encoding_length;
+#[cfg(feature = "ufmt")]
+use ufmt;
+
+use super::biased::{unzigzag, zigzag};
+use super::{decoded_len, encoding_length, EncodeError};
 
 /// Everything that can go wrong when decoding data.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -11,11 +15,49 @@ pub enum DecodeError {
     NonCanonical,
 }
 
+#[cfg(feature = "ufmt")]
+impl ufmt::uDebug for DecodeError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<W>) -> Result<(), W::Error>
+        where W: ufmt::uWrite + ?Sized
+    {
+        match self {
+            DecodeError::NonCanonical => f.write_str("NonCanonical"),
+        }
+    }
+}
+
+/// What a `Decoder` does with a byte, or with a value it has finished.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeEvent {
+    /// The first byte of an encoding, followed by `payload_length` further bytes.
+    TagByte { byte: u8, payload_length: usize },
+    /// A byte following the tag byte, `index` counts from zero.
+    PayloadByte { byte: u8, index: usize },
+    /// The last byte of an encoding has been processed.
+    ValueComplete(u64),
+    /// The last byte of an encoding has been processed, but the encoding is invalid.
+    Error(DecodeError),
+}
+
+/// Receives the events of `Decoder::decode_observed`, for example to show how each byte of
+/// some input was interpreted.
+pub trait DecodeObserver {
+    /// Called for every event, in the order the decoder works.
+    fn event(&mut self, event: DecodeEvent);
+}
+
+impl<F: FnMut(DecodeEvent)> DecodeObserver for F {
+    fn event(&mut self, event: DecodeEvent) {
+        self(event)
+    }
+}
+
 /// State for the nonblocking decoding.
 pub struct Decoder {
     val: u64, // This accumulates parsed data until it contains the correct value.
     total_length: usize, // How many bytes does this varu64 take up in total? A value of 0 indicates the initial state.
     parsed: usize, // How many bytes have we parsed already?
+    lenient: bool, // Accept noncanonical encodings?
 }
 
 impl Decoder {
@@ -24,9 +66,16 @@ impl Decoder {
             val: 0,
             total_length: 0,
             parsed: 0,
+            lenient: false,
         }
     }
 
+    /// Create a decoder that accepts noncanonical encodings, yielding their value instead of a
+    /// `NonCanonical` error. This is the nonblocking counterpart of `decode_relaxed`.
+    pub fn lenient() -> Decoder {
+        Decoder { lenient: true, ..Decoder::new() }
+    }
+
     /// Decode a VarU64 from the input. The decoder can be reused as many times as you want.
     ///
     /// Returns how many bytes have been read. A `None` is returned if more input is needed.
@@ -34,12 +83,54 @@ impl Decoder {
         self.do_decode(input, 0)
     }
 
+    /// Like `decode`, but for signed numbers encoded as by `biased::encode_i64`.
+    pub fn decode_i64(&mut self, input: &[u8]) -> (usize, Option<Result<i64, DecodeError>>) {
+        let (consumed, result) = self.decode(input);
+        (consumed, result.map(|r| r.map(unzigzag)))
+    }
+
+    /// Like `decode`, but reports every step to the `observer`. This processes the input one
+    /// byte at a time, so it is slower than `decode`.
+    pub fn decode_observed<O>(&mut self,
+                              input: &[u8],
+                              observer: &mut O)
+                              -> (usize, Option<Result<u64, DecodeError>>)
+        where O: DecodeObserver + ?Sized
+    {
+        for (i, b) in input.iter().enumerate() {
+            if self.total_length == 0 {
+                observer.event(DecodeEvent::TagByte {
+                                   byte: *b,
+                                   payload_length: decoded_len(*b) - 1,
+                               });
+            } else {
+                observer.event(DecodeEvent::PayloadByte {
+                                   byte: *b,
+                                   index: self.parsed,
+                               });
+            }
+
+            match self.do_decode(&input[i..i + 1], 0) {
+                (_, None) => {}
+                (_, Some(result)) => {
+                    observer.event(match result {
+                                       Ok(n) => DecodeEvent::ValueComplete(n),
+                                       Err(e) => DecodeEvent::Error(e),
+                                   });
+                    return (i + 1, Some(result));
+                }
+            }
+        }
+
+        (input.len(), None)
+    }
+
     pub fn do_decode(&mut self,
                      input: &[u8],
                      total_consumed: usize)
                      -> (usize, Option<Result<u64, DecodeError>>) {
         if input.len() == 0 {
-            return (0, None);
+            return (total_consumed, None);
         }
 
         let b = input[0];
@@ -104,15 +195,16 @@ impl Decoder {
             self.parsed += 1;
 
             if self.parsed == self.total_length {
-                if self.parsed > encoding_length(self.val) - 1 {
-                    self.reset();
-                    return (total_consumed + 1, Some(Err(DecodeError::NonCanonical)));
+                let val = self.val;
+                let canonical = self.parsed < encoding_length(val);
+                self.reset();
+                if canonical || self.lenient {
+                    return (total_consumed + 1, Some(Ok(val)));
                 } else {
-                    self.reset();
-                    return (total_consumed + 1, Some(Ok(self.val)));
+                    return (total_consumed + 1, Some(Err(DecodeError::NonCanonical)));
                 }
             } else {
-                return self.decode(&input[1..]);
+                return self.do_decode(&input[1..], total_consumed + 1);
             }
         }
     }
@@ -126,7 +218,7 @@ impl Decoder {
 
 /// State for the nonblocking encoding.
 pub struct Encoder {
-    n: u64, // What to encode (or what remains of it).
+    n: u64, // What to encode.
     remaining: usize, // How many bytes do we still need to output? `9` signals that none have been output yet.
 }
 
@@ -136,6 +228,11 @@ impl Encoder {
         Encoder { n, remaining: 9 }
     }
 
+    /// Create an encoder for encoding the given signed number as by `biased::encode_i64`.
+    pub fn new_i64(n: i64) -> Encoder {
+        Encoder::new(zigzag(n))
+    }
+
     /// Encode (potentially only parts of) the number into the output buffer. This returns how
     /// many bytes were written. If it returns zero even though the `out` buffer had non-zero
     /// length, the encoding process is done.
@@ -143,9 +240,26 @@ impl Encoder {
         self.do_encode(out, 0)
     }
 
+    /// Encode the remaining bytes of the number into the output buffer, returning how many
+    /// bytes were written, or an error containing how many bytes are needed if the buffer is
+    /// too small for all of them. In that case, nothing is written.
+    pub fn try_encode(&mut self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        let needed = if self.remaining == 9 {
+            encoding_length(self.n)
+        } else {
+            self.remaining
+        };
+
+        if out.len() < needed {
+            Err(EncodeError::BufferTooSmall(needed))
+        } else {
+            Ok(self.encode(out))
+        }
+    }
+
     fn do_encode(&mut self, out: &mut [u8], total_output: usize) -> usize {
         if out.len() == 0 {
-            return 0;
+            return total_output;
         }
 
         if self.remaining == 0 {
@@ -207,7 +321,6 @@ impl Encoder {
         } else {
             self.remaining -= 1;
             out[0] = (self.n >> (8 * self.remaining)) as u8;
-            self.n >>= 8;
 
             if self.remaining == 0 {
                 return total_output + 1;
@@ -228,7 +341,7 @@ enum _LengthValueDecoder {
 
 // The maximum capacity of the byte vector to preallocate. Even if malicious input claims
 // a longer value, only this much memory will be blindly allocated.
-static MAX_ALLOC: usize = 2048;
+pub(crate) static MAX_ALLOC: usize = 2048;
 
 impl LengthValueDecoder {
     pub fn new() -> LengthValueDecoder {
@@ -289,6 +402,23 @@ pub enum DecodeLimitError {
     Limit { limit: u64, actual: u64 },
 }
 
+#[cfg(feature = "ufmt")]
+impl ufmt::uDebug for DecodeLimitError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<W>) -> Result<(), W::Error>
+        where W: ufmt::uWrite + ?Sized
+    {
+        match self {
+            DecodeLimitError::NonCanonical => f.write_str("NonCanonical"),
+            DecodeLimitError::Limit { limit, actual } => {
+                f.debug_struct("Limit")?
+                    .field("limit", limit)?
+                    .field("actual", actual)?
+                    .finish()
+            }
+        }
+    }
+}
+
 impl From<DecodeError> for DecodeLimitError {
     fn from(e: DecodeError) -> DecodeLimitError {
         match e {
@@ -453,7 +583,7 @@ mod tests {
               let mut dec = super::Decoder::new();
 
               match decode(&data) {
-                  Err((DecodeError::UnexpectedEndOfInput, tail)) => {
+                  Err((DecodeError::UnexpectedEndOfInput(_), tail)) => {
                       let (consumed, tmp) = decode_all(&data, &mut dec, chunk_size as usize);
                       assert!(tmp.unwrap_err().is_none());
                       assert_eq!(consumed, data.len() - tail.len());
@@ -515,6 +645,27 @@ mod tests {
         }
     }
 
+    quickcheck! {
+        fn test_roundtrip_large(n: u64, shift: u8, chunk_size: u8) -> bool {
+            // Arbitrary u64s are small, shift them to cover all encoding lengths.
+            let n = n.rotate_left(shift as u32);
+            let chunk_size = (chunk_size as usize % 9) + 1;
+
+            let mut buf = [0u8; 9];
+            let written = encode(n, &mut buf);
+
+            let mut nb_buf = [0u8; 9];
+            let mut enc = super::Encoder::new(n);
+            assert_eq!(encode_all(&mut enc, &mut nb_buf.chunks_mut(chunk_size)), written);
+            assert_eq!(&nb_buf[..written], &buf[..written]);
+
+            let mut dec = super::Decoder::new();
+            assert_eq!(decode_all(&buf[..written], &mut dec, chunk_size), (written, Ok(n)));
+
+            true
+        }
+    }
+
     fn length_value_decode_all(data: &[u8],
                                dec: &mut super::LengthValueDecoder,
                                chunk_size: usize)
@@ -539,7 +690,7 @@ mod tests {
               let mut dec = super::LengthValueDecoder::new();
 
               match decode(&data) {
-                  Err((DecodeError::UnexpectedEndOfInput, tail)) => {
+                  Err((DecodeError::UnexpectedEndOfInput(_), tail)) => {
                       let (consumed, tmp) = length_value_decode_all(&data, &mut dec, chunk_size as usize);
                       assert!(tmp.unwrap_err().is_none());
                       assert_eq!(consumed, data.len() - tail.len());
@@ -571,6 +722,99 @@ mod tests {
           }
       }
 
+    #[test]
+    fn test_try_encode() {
+        use super::Encoder;
+
+        let mut enc = Encoder::new(256);
+        let mut buf = [0u8; 3];
+        assert_eq!(enc.try_encode(&mut buf[..2]), Err(EncodeError::BufferTooSmall(3)));
+        assert_eq!(enc.encode(&mut buf[..1]), 1);
+        assert_eq!(enc.try_encode(&mut buf[..1]), Err(EncodeError::BufferTooSmall(2)));
+        assert_eq!(enc.try_encode(&mut buf[1..]), Ok(2));
+        assert_eq!(buf, [249, 1, 0]);
+        assert_eq!(enc.try_encode(&mut buf[..]), Ok(0));
+    }
+
+    #[test]
+    fn test_decode_observed() {
+        use super::{Decoder, DecodeError, DecodeEvent};
+
+        let mut events = Vec::new();
+        let mut dec = Decoder::new();
+        assert_eq!(dec.decode_observed(&[7, 249], &mut |e| events.push(e)), (1, Some(Ok(7))));
+        assert_eq!(dec.decode_observed(&[249, 1], &mut |e| events.push(e)), (2, None));
+        assert_eq!(dec.decode_observed(&[0, 248, 3], &mut |e| events.push(e)),
+                   (1, Some(Ok(256))));
+        assert_eq!(dec.decode_observed(&[248, 3], &mut |e| events.push(e)),
+                   (2, Some(Err(DecodeError::NonCanonical))));
+
+        assert_eq!(events,
+                   vec![DecodeEvent::TagByte {
+                            byte: 7,
+                            payload_length: 0,
+                        },
+                        DecodeEvent::ValueComplete(7),
+                        DecodeEvent::TagByte {
+                            byte: 249,
+                            payload_length: 2,
+                        },
+                        DecodeEvent::PayloadByte { byte: 1, index: 0 },
+                        DecodeEvent::PayloadByte { byte: 0, index: 1 },
+                        DecodeEvent::ValueComplete(256),
+                        DecodeEvent::TagByte {
+                            byte: 248,
+                            payload_length: 1,
+                        },
+                        DecodeEvent::PayloadByte { byte: 3, index: 0 },
+                        DecodeEvent::Error(DecodeError::NonCanonical)]);
+    }
+
+    #[test]
+    fn test_i64() {
+        use super::{Decoder, DecodeError, Encoder};
+
+        let mut enc = Encoder::new_i64(124);
+        let mut buf = [0u8; 2];
+        assert_eq!(enc.encode(&mut buf[..1]), 1);
+        assert_eq!(enc.encode(&mut buf[1..]), 1);
+        assert_eq!(buf, [248, 248]);
+
+        let mut dec = Decoder::new();
+        assert_eq!(dec.decode_i64(&buf[..1]), (1, None));
+        assert_eq!(dec.decode_i64(&buf[1..]), (1, Some(Ok(124))));
+        assert_eq!(dec.decode_i64(&[248, 3]), (2, Some(Err(DecodeError::NonCanonical))));
+    }
+
+    #[test]
+    fn test_lenient() {
+        use super::Decoder;
+
+        let mut dec = Decoder::lenient();
+        assert_eq!(dec.decode(&[248, 3, 250]), (2, Some(Ok(3))));
+        assert_eq!(dec.decode(&[250, 0]), (2, None));
+        assert_eq!(dec.decode(&[1, 0, 7]), (2, Some(Ok(256))));
+        assert_eq!(dec.decode(&[249, 1, 44]), (3, Some(Ok(300))));
+    }
+
+    #[cfg(feature = "ufmt")]
+    #[test]
+    fn test_ufmt() {
+        use super::super::tests::UString;
+
+        let errs = [super::DecodeLimitError::NonCanonical,
+                    super::DecodeLimitError::Limit { limit: 1, actual: 2 }];
+        for e in errs.iter() {
+            let mut debug = UString(String::new());
+            ufmt::uwrite!(debug, "{:?}", e).unwrap();
+            assert_eq!(debug.0, format!("{:?}", e));
+        }
+
+        let mut debug = UString(String::new());
+        ufmt::uwrite!(debug, "{:?}", super::DecodeError::NonCanonical).unwrap();
+        assert_eq!(debug.0, "NonCanonical");
+    }
+
       fn length_value_encode_all<'a, I: Iterator<Item = &'a mut [u8]>, T: AsRef<[u8]>>(enc: &mut super::LengthValueEncoder<T>, outs: &mut I)
 -> usize{
         let mut total_written = 0;
@@ -0,0 +1,208 @@
+//! A growable collection of numbers, stored as their concatenated varu64 encodings.
+
+use std::iter::FromIterator;
+
+use super::{decode, encode_append, DecodeError};
+
+/// A growable sequence of `u64`s that stores its values as concatenated varu64 encodings, so
+/// small values take up a single byte each.
+///
+/// Accessing a value by index requires decoding all values before it. A `VaruVec` created with
+/// `with_sampling` additionally remembers the offset of every `interval`-th value, so that `get`
+/// only decodes at most `interval - 1` values before the requested one.
+#[derive(Debug, Clone, Default)]
+pub struct VaruVec {
+    bytes: Vec<u8>,
+    len: usize,
+    last: Option<u64>,
+    interval: usize, // Zero if no offsets are sampled.
+    samples: Vec<usize>, // Offset into `bytes` of the values at multiples of `interval`.
+}
+
+impl VaruVec {
+    /// Create an empty `VaruVec`.
+    pub fn new() -> VaruVec {
+        VaruVec::default()
+    }
+
+    /// Create an empty `VaruVec` that remembers the offset of every `interval`-th value, using
+    /// one `usize` per `interval` values for faster access by index.
+    ///
+    /// # Panics
+    /// Panics if `interval` is zero.
+    pub fn with_sampling(interval: usize) -> VaruVec {
+        assert!(interval > 0, "interval must be positive");
+        VaruVec {
+            interval,
+            ..VaruVec::default()
+        }
+    }
+
+    /// Create a `VaruVec` from the concatenated varu64 encodings in `bytes`.
+    ///
+    /// # Errors
+    /// On error, this returns the error of the first invalid encoding, and the offset into
+    /// `bytes` at which that encoding starts.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<VaruVec, (DecodeError, usize)> {
+        let mut v = VaruVec::new();
+        let mut remaining = &bytes[..];
+        while !remaining.is_empty() {
+            match decode(remaining) {
+                Ok((n, tail)) => {
+                    v.len += 1;
+                    v.last = Some(n);
+                    remaining = tail;
+                }
+                Err((e, _)) => return Err((e, bytes.len() - remaining.len())),
+            }
+        }
+        v.bytes = bytes;
+        Ok(v)
+    }
+
+    /// Append a value.
+    pub fn push(&mut self, n: u64) {
+        if self.interval > 0 && self.len.is_multiple_of(self.interval) {
+            self.samples.push(self.bytes.len());
+        }
+        encode_append(n, &mut self.bytes);
+        self.len += 1;
+        self.last = Some(n);
+    }
+
+    /// Return the value at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<u64> {
+        if index >= self.len {
+            return None;
+        }
+
+        let (skip, offset) = if self.interval > 0 {
+            (index % self.interval, self.samples[index / self.interval])
+        } else {
+            (index, 0)
+        };
+        Iter { bytes: &self.bytes[offset..] }.nth(skip)
+    }
+
+    /// Return the last value, or `None` if there are none.
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Return the number of values.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return whether there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return an iterator over the values.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { bytes: &self.bytes }
+    }
+
+    /// Return the concatenated encodings of the values.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Two `VaruVec`s are equal if they contain the same values, regardless of sampling.
+impl PartialEq for VaruVec {
+    fn eq(&self, other: &VaruVec) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for VaruVec {}
+
+impl Extend<u64> for VaruVec {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        for n in iter {
+            self.push(n);
+        }
+    }
+}
+
+impl FromIterator<u64> for VaruVec {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> VaruVec {
+        let mut v = VaruVec::new();
+        v.extend(iter);
+        v
+    }
+}
+
+impl<'a> IntoIterator for &'a VaruVec {
+    type Item = u64;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// An iterator over the values of a `VaruVec`. Created by `VaruVec::iter`.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    bytes: &'a [u8], // The encodings of the remaining values, which are known to be valid.
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        let (n, tail) = decode(self.bytes).unwrap();
+        self.bytes = tail;
+        Some(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let mut v = VaruVec::new();
+        assert!(v.is_empty());
+        assert_eq!(v.last(), None);
+        v.push(7);
+        v.extend(vec![300, 0]);
+        assert_eq!(v.len(), 3);
+        assert_eq!(v.last(), Some(0));
+        assert_eq!(v.as_bytes(), &[7, 249, 1, 44, 0]);
+        assert_eq!(v.iter().collect::<Vec<u64>>(), vec![7, 300, 0]);
+        assert_eq!((v.get(1), v.get(3)), (Some(300), None));
+
+        let mut sampled = VaruVec::with_sampling(2);
+        sampled.extend(vec![7, 300, 0]);
+        assert_eq!(sampled, v);
+        assert_eq!((sampled.get(0), sampled.get(1), sampled.get(2)), (Some(7), Some(300), Some(0)));
+
+        assert_eq!(VaruVec::from_bytes(vec![7, 249, 1, 44, 0]), Ok(v));
+        assert_eq!(VaruVec::from_bytes(vec![7, 248, 1]), Err((NonCanonical(1), 1)));
+    }
+
+    quickcheck! {
+        fn test_get(values: Vec<u64>, interval: usize) -> bool {
+            let values = spread_all(&values);
+            let v: VaruVec = values.iter().cloned().collect();
+            let mut sampled = VaruVec::with_sampling(interval % 5 + 1);
+            sampled.extend(values.iter().cloned());
+
+            v.len() == values.len() && v.last() == values.last().cloned() &&
+            (&v).into_iter().collect::<Vec<u64>>() == values &&
+            (0..values.len() + 1).all(|i| v.get(i) == values.get(i).cloned() &&
+                                          sampled.get(i) == values.get(i).cloned())
+        }
+    }
+}
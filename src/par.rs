@@ -0,0 +1,100 @@
+//! Parallel encoding and decoding of large buffers, available with the `rayon` feature.
+
+use rayon::prelude::*;
+
+use super::{decode_all, decoded_len, encode_slice, DecodeError};
+
+// How many values or bytes each parallel task handles.
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Encodes the `values` one after the other, appending them to `out` and returning how many
+/// bytes have been written. Produces the same bytes as `encode_slice`, encoding chunks of the
+/// values in parallel.
+pub fn par_encode_slice(values: &[u64], out: &mut Vec<u8>) -> usize {
+    let chunks: Vec<Vec<u8>> = values
+        .par_chunks(CHUNK_SIZE)
+        .map(|chunk| {
+                 let mut encoded = Vec::new();
+                 encode_slice(chunk, &mut encoded);
+                 encoded
+             })
+        .collect();
+
+    let len = chunks.iter().map(|chunk| chunk.len()).sum();
+    out.reserve(len);
+    for chunk in chunks.iter() {
+        out.extend_from_slice(chunk);
+    }
+    len
+}
+
+/// Decode all concatenated varu64s in the `input`, like `decode_all` but in parallel.
+///
+/// Since an encoding can only be found by parsing all encodings before it, a first pass walks
+/// the tag bytes to split the `input` into chunks that start at encoding boundaries. The chunks
+/// are then decoded in parallel.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts.
+pub fn par_decode_all(input: &[u8]) -> Result<Vec<u64>, (DecodeError, usize)> {
+    let mut starts = vec![0];
+    let mut offset = 0;
+    while offset < input.len() {
+        if offset - starts[starts.len() - 1] >= CHUNK_SIZE {
+            starts.push(offset);
+        }
+        offset += decoded_len(input[offset]);
+    }
+    let mut ends: Vec<usize> = starts[1..].to_vec();
+    ends.push(input.len());
+
+    let chunks: Vec<Result<Vec<u64>, (DecodeError, usize)>> = starts
+        .par_iter()
+        .zip(ends.par_iter())
+        .map(|(start, end)| {
+                 decode_all(&input[*start..*end]).map_err(|(e, offset)| (e, start + offset))
+             })
+        .collect();
+
+    let mut values = Vec::new();
+    for chunk in chunks {
+        values.extend_from_slice(&chunk?);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let values: Vec<u64> = (0..3 * CHUNK_SIZE as u64).map(|n| n * n).collect();
+        let mut out = vec![42];
+        let len = par_encode_slice(&values, &mut out);
+
+        let mut expected = vec![42];
+        assert_eq!(encode_slice(&values, &mut expected), len);
+        assert_eq!(out, expected);
+        assert_eq!(par_decode_all(&out[1..]), Ok(values));
+
+        out.truncate(out.len() - 1);
+        assert_eq!(par_decode_all(&out[1..]), decode_all(&out[1..]));
+        out[CHUNK_SIZE + 7] = 248;
+        out[CHUNK_SIZE + 8] = 0;
+        assert_eq!(par_decode_all(&out[1..]), decode_all(&out[1..]));
+        assert_eq!(par_decode_all(&[7, 248, 1]), Err((NonCanonical(1), 1)));
+    }
+
+    quickcheck! {
+        fn test_par_agrees(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut out = Vec::new();
+            par_encode_slice(&values, &mut out);
+            par_decode_all(&out) == Ok(values)
+        }
+    }
+}
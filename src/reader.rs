@@ -0,0 +1,310 @@
+//! Decoding from `std::io` readers.
+
+use std::cmp::min;
+use std::io;
+
+use super::{decode, decoded_len, DecodeError};
+
+/// Read and decode the next varu64 from the reader. This reads the tag byte and then exactly the
+/// payload bytes, so no data after the encoding is consumed.
+///
+/// A reader that ends before or within the encoding results in an error of kind
+/// `UnexpectedEof`, invalid encodings in an error of kind `InvalidData`, see
+/// `impl From<DecodeError> for io::Error`. Interrupted reads are retried as described in the
+/// [crate docs](../index.html#io).
+pub fn decode_read<R: io::Read + ?Sized>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 9];
+    r.read_exact(&mut buf[..1])?;
+    let len = decoded_len(buf[0]);
+    r.read_exact(&mut buf[1..len])?;
+
+    match decode(&buf[..len]) {
+        Ok((n, _)) => Ok(n),
+        Err((e, _)) => Err(e.into()),
+    }
+}
+
+/// Decode the next varu64 from the buffered data of the reader without consuming it, returning
+/// the value and the length of its encoding.
+///
+/// If the reader's buffer is empty, it is filled first. `BufRead` provides no way of reading
+/// more data into a nonempty buffer without consuming, so if the buffer ends within the
+/// encoding, this returns `None`, whether the reader has more data or not. The encoding can then
+/// be read with `decode_read`, or with `Varu64Reader`.
+///
+/// A reader that ends before the first byte of the encoding results in an error of kind
+/// `UnexpectedEof`, invalid encodings in an error of kind `InvalidData`, see
+/// `impl From<DecodeError> for io::Error`.
+pub fn peek_varu64<R: io::BufRead + ?Sized>(r: &mut R) -> io::Result<Option<(u64, usize)>> {
+    let buf = loop {
+        match r.fill_buf() {
+            Ok(buf) => break buf,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    };
+
+    match decode(buf) {
+        Ok((n, tail)) => Ok(Some((n, buf.len() - tail.len()))),
+        Err((DecodeError::UnexpectedEndOfInput(_), _)) if !buf.is_empty() => Ok(None),
+        Err((e, _)) => Err(e.into()),
+    }
+}
+
+/// An iterator over the varu64s of a buffered reader. Created by `Varu64Reader::new`.
+///
+/// Encodings that straddle refills of the reader's buffer are handled. The iterator ends when
+/// the reader ends before the first byte of an encoding. A reader that ends within an encoding
+/// results in an error of kind `UnexpectedEof`, an invalid encoding in an error of kind
+/// `InvalidData`. After an invalid encoding, iteration continues with the next one.
+/// Interrupted reads are retried as described in the [crate docs](../index.html#io).
+///
+/// Other errors of the reader are yielded as they are. The bytes of an encoding read before
+/// such an error are kept, so if iteration continues afterwards, it resumes within that
+/// encoding.
+#[derive(Debug)]
+pub struct Varu64Reader<R> {
+    r: R,
+    encoding: [u8; 9],
+    len: usize, // How many bytes of the current encoding have been read.
+}
+
+impl<R: io::BufRead> Varu64Reader<R> {
+    /// Create an iterator over the varu64s of the reader.
+    pub fn new(r: R) -> Varu64Reader<R> {
+        Varu64Reader {
+            r,
+            encoding: [0; 9],
+            len: 0,
+        }
+    }
+
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.r
+    }
+
+    /// Return the underlying reader. The bytes of a partially read encoding are lost.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    fn read_next(&mut self) -> io::Result<Option<u64>> {
+        // The length of the encoding, known after reading its first byte.
+        let mut total = if self.len == 0 { 1 } else { decoded_len(self.encoding[0]) };
+
+        while self.len < total {
+            let buf = fill_buf(&mut self.r)?;
+            if buf.is_empty() {
+                return match self.len {
+                    0 => Ok(None),
+                    _ => {
+                        self.len = 0;
+                        Err(io::ErrorKind::UnexpectedEof.into())
+                    }
+                };
+            }
+            if self.len == 0 {
+                total = decoded_len(buf[0]);
+            }
+
+            let available = min(total - self.len, buf.len());
+            self.encoding[self.len..self.len + available].copy_from_slice(&buf[..available]);
+            self.r.consume(available);
+            self.len += available;
+        }
+
+        self.len = 0;
+        match decode(&self.encoding[..total]) {
+            Ok((n, _)) => Ok(Some(n)),
+            Err((e, _)) => Err(e.into()),
+        }
+    }
+}
+
+impl<R: io::BufRead> Iterator for Varu64Reader<R> {
+    type Item = io::Result<u64>;
+
+    fn next(&mut self) -> Option<io::Result<u64>> {
+        match self.read_next() {
+            Ok(Some(n)) => Some(Ok(n)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+// Fill the buffer of the reader, retrying interrupted reads.
+fn fill_buf<R: io::BufRead + ?Sized>(r: &mut R) -> io::Result<&[u8]> {
+    loop {
+        match r.fill_buf() {
+            Ok([]) => return Ok(&[]),
+            Ok(_) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    // The buffer is nonempty now, so this returns it without reading. Returning the buffer from
+    // within the loop is rejected by the borrow checker.
+    r.fill_buf()
+}
+
+/// Skip the next encoding of the reader by reading its tag byte and seeking past the payload,
+/// returning the length of the encoding. Like `skip`, this neither decodes the number nor
+/// checks its canonicity.
+///
+/// An empty reader results in an error of kind `UnexpectedEof`. Since seeking past the end is
+/// allowed, a payload cut off by the end of the reader is not detected.
+pub fn skip_read<R: io::Read + io::Seek + ?Sized>(r: &mut R) -> io::Result<usize> {
+    let mut tag = [0u8; 1];
+    r.read_exact(&mut tag)?;
+
+    let len = decoded_len(tag[0]);
+    if len > 1 {
+        r.seek(io::SeekFrom::Current(len as i64 - 1))?;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+    use super::*;
+    use super::super::encode_append;
+    use super::super::tests::{FlakyReader, spread_all};
+
+    #[test]
+    fn test_decode_read() {
+        let mut r = &[7, 249, 1, 0, 248, 2, 5, 250, 1][..];
+        assert_eq!(decode_read(&mut r).unwrap(), 7);
+        assert_eq!(decode_read(&mut r).unwrap(), 256);
+        assert_eq!(decode_read(&mut r).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode_read(&mut r).unwrap(), 5);
+        assert_eq!(decode_read(&mut r).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(decode_read(&mut r).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_varu64_reader() {
+        let r = BufReader::with_capacity(2, &[7, 249, 1, 0, 248, 2, 5, 250, 1][..]);
+        let kinds: Vec<Result<u64, io::ErrorKind>> = Varu64Reader::new(r)
+            .map(|n| n.map_err(|e| e.kind()))
+            .collect();
+        assert_eq!(kinds,
+                   vec![Ok(7),
+                        Ok(256),
+                        Err(io::ErrorKind::InvalidData),
+                        Ok(5),
+                        Err(io::ErrorKind::UnexpectedEof)]);
+
+        let mut reader = Varu64Reader::new(&[1, 2][..]);
+        assert_eq!(reader.next().unwrap().unwrap(), 1);
+        assert_eq!(reader.into_inner(), &[2][..]);
+    }
+
+    // A reader that returns at most one byte per call, and fails with an error of kind `Other`
+    // on every other call.
+    struct FailingReader<'a> {
+        data: &'a [u8],
+        fail: bool,
+    }
+
+    impl<'a> io::Read for FailingReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.fail = !self.fail;
+            if self.fail {
+                return Err(io::Error::other("failing"));
+            }
+            (&mut self.data).take(1).read(buf)
+        }
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut r = BufReader::with_capacity(4, &[7, 249, 1, 0, 248, 2][..]);
+        assert_eq!(peek_varu64(&mut r).unwrap(), Some((7, 1)));
+        assert_eq!(peek_varu64(&mut r).unwrap(), Some((7, 1)));
+        r.consume(1);
+        assert_eq!(peek_varu64(&mut r).unwrap(), Some((256, 3)));
+        r.consume(3);
+        assert_eq!(peek_varu64(&mut r).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        r.consume(2);
+        assert_eq!(peek_varu64(&mut r).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        // The buffer ends within the encoding, but the reader does not.
+        let mut r = BufReader::with_capacity(2, &[249, 1, 0][..]);
+        assert_eq!(peek_varu64(&mut r).unwrap(), None);
+        assert_eq!(decode_read(&mut r).unwrap(), 256);
+
+        // The reader ends within the encoding.
+        let mut r = BufReader::new(&[249, 1][..]);
+        assert_eq!(peek_varu64(&mut r).unwrap(), None);
+        assert_eq!(decode_read(&mut r).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        // The first read is interrupted, and each fill only buffers a single byte.
+        let mut r = BufReader::new(FlakyReader::new(&[7, 248, 255]));
+        assert_eq!(peek_varu64(&mut r).unwrap(), Some((7, 1)));
+        r.consume(1);
+        assert_eq!(peek_varu64(&mut r).unwrap(), None);
+    }
+
+    #[test]
+    fn test_skip_read() {
+        let mut r = Cursor::new(vec![7, 249, 1, 0, 248, 5, 3]);
+        assert_eq!(skip_read(&mut r).unwrap(), 1);
+        assert_eq!(skip_read(&mut r).unwrap(), 3);
+        assert_eq!(skip_read(&mut r).unwrap(), 2);
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![3]);
+        assert_eq!(skip_read(&mut r).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    quickcheck! {
+        fn test_decode_read_flaky(values: Vec<u64>) -> bool {
+            let values = spread_all(&values);
+            let mut data = Vec::new();
+            for n in values.iter() {
+                encode_append(*n, &mut data);
+            }
+
+            let mut r = FlakyReader::new(&data);
+            for n in values.iter() {
+                assert_eq!(decode_read(&mut r).unwrap(), *n);
+            }
+            decode_read(&mut r).unwrap_err().kind() == io::ErrorKind::UnexpectedEof
+        }
+
+        fn test_varu64_reader_chunked(values: Vec<u64>, capacity: usize) -> bool {
+            let values = spread_all(&values);
+            let mut data = Vec::new();
+            for n in values.iter() {
+                encode_append(*n, &mut data);
+            }
+
+            let r = BufReader::with_capacity(capacity % 12 + 1, &data[..]);
+            let from_chunks: Vec<u64> = Varu64Reader::new(r).map(|n| n.unwrap()).collect();
+
+            let r = BufReader::with_capacity(capacity % 12 + 1, FlakyReader::new(&data));
+            let from_flaky: Vec<u64> = Varu64Reader::new(r).map(|n| n.unwrap()).collect();
+
+            // Resuming after every error must not lose any bytes.
+            let r = FailingReader {
+                data: &data,
+                fail: false,
+            };
+            let from_failing: Vec<u64> = Varu64Reader::new(BufReader::with_capacity(1, r))
+                .filter_map(|n| match n {
+                                Ok(n) => Some(n),
+                                Err(e) => {
+                                    assert_eq!(e.kind(), io::ErrorKind::Other);
+                                    None
+                                }
+                            })
+                .collect();
+
+            from_chunks == values && from_flaky == values && from_failing == values
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! A deliberately naive reference implementation, and a harness cross-checking the optimized
+//! code paths against it. Available with the `reference` feature, for fuzzing and regression
+//! tests in downstream crates.
+//!
+//! The reference code follows the spec as literally as possible, trading speed for being easy
+//! to verify by reading.
+
+use std::{cmp, fmt};
+
+use super::{decode, decode_unchecked, encode, encode_write, nb, DecodeError};
+use super::batch::{decode_batch, encode_batch};
+#[cfg(feature = "rayon")]
+use super::par::{par_decode_all, par_encode_slice};
+use super::source::{ChunksSource, SourceError, ValuesFrom};
+
+/// Encode `n`, returning the bytes of its encoding.
+pub fn reference_encode(n: u64) -> Vec<u8> {
+    if n < 248 {
+        return vec![n as u8];
+    }
+
+    // The big-endian bytes of n, without leading zero bytes.
+    let mut payload = Vec::new();
+    let mut rest = n;
+    while rest > 0 {
+        payload.insert(0, (rest % 256) as u8);
+        rest /= 256;
+    }
+
+    let mut out = vec![247 + payload.len() as u8];
+    out.extend_from_slice(&payload);
+    out
+}
+
+/// Decode a single varu64 from the start of `input`, returning the value and the length of its
+/// encoding.
+///
+/// # Errors
+/// On error, this also returns the length of the data that was parsed, with the same semantics
+/// as `decode`.
+pub fn reference_decode(input: &[u8]) -> Result<(u64, usize), (DecodeError, usize)> {
+    if input.is_empty() {
+        return Err((DecodeError::UnexpectedEndOfInput(1), 0));
+    }
+
+    let tag = input[0];
+    if tag < 248 {
+        return Ok((tag as u64, 1));
+    }
+
+    let payload_len = (tag - 247) as usize;
+    if input.len() < 1 + payload_len {
+        return Err((DecodeError::UnexpectedEndOfInput(1 + payload_len - input.len()),
+                    input.len()));
+    }
+
+    let mut n: u64 = 0;
+    for b in input[1..1 + payload_len].iter() {
+        n = n * 256 + *b as u64;
+    }
+
+    if reference_encode(n).len() != 1 + payload_len {
+        return Err((DecodeError::NonCanonical(n), 1 + payload_len));
+    }
+    Ok((n, 1 + payload_len))
+}
+
+/// A code path that disagrees with the reference implementation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Mismatch {
+    /// The name of the code path.
+    pub path: &'static str,
+    /// The offset into the checked input or output at which the disagreement was found, or the
+    /// value whose encoding disagrees.
+    pub at: u64,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        write!(f, "{} disagrees with the reference at {}", self.path, self.at)
+    }
+}
+
+/// Check that all encoding paths produce the reference encoding of `n`, writing to the
+/// nonblocking encoder in chunks of `chunk_size` bytes (at least one).
+pub fn cross_check_encode(n: u64, chunk_size: usize) -> Result<(), Mismatch> {
+    let expected = reference_encode(n);
+    let mismatch = |path| Mismatch { path, at: n };
+
+    let mut buf = [0u8; 9];
+    let len = encode(n, &mut buf[..]);
+    if buf[..len] != expected[..] {
+        return Err(mismatch("encode"));
+    }
+
+    let mut written = Vec::new();
+    encode_write(n, &mut written).map_err(|_| mismatch("encode_write"))?;
+    if written != expected {
+        return Err(mismatch("encode_write"));
+    }
+
+    let mut enc = nb::Encoder::new(n);
+    let mut chunked = Vec::new();
+    let mut chunk = vec![0u8; if chunk_size == 0 { 1 } else { chunk_size }];
+    loop {
+        let len = enc.encode(&mut chunk);
+        if len == 0 || chunked.len() > 9 {
+            break;
+        }
+        chunked.extend_from_slice(&chunk[..len]);
+    }
+    if chunked != expected {
+        return Err(mismatch("nb::Encoder"));
+    }
+
+    Ok(())
+}
+
+/// Check that all paths encoding many values at once produce the concatenated reference
+/// encodings of the `values`.
+pub fn cross_check_encode_all(values: &[u64]) -> Result<(), Mismatch> {
+    let mut expected = Vec::new();
+    for n in values.iter() {
+        expected.extend_from_slice(&reference_encode(*n));
+    }
+
+    let mut batch = Vec::new();
+    let len = encode_batch(values, &mut batch);
+    if len != batch.len() || batch != expected {
+        return Err(Mismatch {
+                       path: "batch::encode_batch",
+                       at: first_difference(&batch, &expected) as u64,
+                   });
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        let mut par = Vec::new();
+        let len = par_encode_slice(values, &mut par);
+        if len != par.len() || par != expected {
+            return Err(Mismatch {
+                           path: "par::par_encode_slice",
+                           at: first_difference(&par, &expected) as u64,
+                       });
+        }
+    }
+
+    Ok(())
+}
+
+// The offset of the first byte in which a and b differ, or the length of the shorter one.
+fn first_difference(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+/// Check that all decoding paths agree with the reference on the `input`, consisting of
+/// concatenated varu64s (valid or not), feeding the chunked decoders `chunk_size` bytes (at
+/// least one) at a time. The batch decoder is given room for `chunk_size` values per call.
+pub fn cross_check_decode(input: &[u8], chunk_size: usize) -> Result<(), Mismatch> {
+    let chunk_size = if chunk_size == 0 { 1 } else { chunk_size };
+    let chunks: Vec<&[u8]> = input.chunks(chunk_size).collect();
+    let mut values = ValuesFrom::new(ChunksSource::new(&chunks));
+    let mut nb_dec = nb::Decoder::new();
+    let mut nb_offset = 0;
+    let mut valid = Vec::new(); // The values before the first invalid encoding.
+    let mut first_error = None;
+
+    let mut offset = 0;
+    while offset < input.len() {
+        let mismatch = |path| Mismatch { path, at: offset as u64 };
+        let expected = reference_decode(&input[offset..]);
+        let expected_len = match expected {
+            Ok((_, len)) | Err((_, len)) => len,
+        };
+        match expected {
+            Ok((n, _)) if first_error.is_none() => valid.push(n),
+            Err((e, _)) if first_error.is_none() => first_error = Some((e, offset)),
+            _ => {}
+        }
+
+        let actual = match decode(&input[offset..]) {
+            Ok((n, tail)) => Ok((n, input.len() - offset - tail.len())),
+            Err((e, tail)) => Err((e, input.len() - offset - tail.len())),
+        };
+        if actual != expected {
+            return Err(mismatch("decode"));
+        }
+
+        // The unchecked decoder yields the value of noncanonical encodings.
+        let unchecked = match decode_unchecked(&input[offset..]) {
+            Ok((n, tail)) => Ok((n, input.len() - offset - tail.len())),
+            Err((e, tail)) => Err((e, input.len() - offset - tail.len())),
+        };
+        let agrees = match (unchecked, expected) {
+            (Ok((n, len)), Err((DecodeError::NonCanonical(m), expected_len))) => {
+                n == m && len == expected_len
+            }
+            (unchecked, expected) => unchecked == expected,
+        };
+        if !agrees {
+            return Err(mismatch("decode_unchecked"));
+        }
+
+        let from_source = match values.next() {
+            Some(Ok(n)) => Ok(n),
+            Some(Err(SourceError::Decode(e))) => Err(e),
+            _ => return Err(mismatch("source::ValuesFrom")),
+        };
+        if from_source != expected.map(|(n, _)| n).map_err(|(e, _)| e) {
+            return Err(mismatch("source::ValuesFrom"));
+        }
+
+        // The nonblocking decoder reports neither the value of noncanonical encodings nor
+        // incomplete ones.
+        let mut from_nb = None;
+        while from_nb.is_none() && nb_offset < input.len() {
+            let end = if nb_offset + chunk_size < input.len() {
+                nb_offset + chunk_size
+            } else {
+                input.len()
+            };
+            let (consumed, result) = nb_dec.decode(&input[nb_offset..end]);
+            nb_offset += consumed;
+            from_nb = result;
+        }
+        let agrees = match (from_nb, expected) {
+            (Some(Ok(n)), Ok((m, _))) => n == m,
+            (Some(Err(nb::DecodeError::NonCanonical)), Err((DecodeError::NonCanonical(_), _))) => {
+                true
+            }
+            (None, Err((DecodeError::UnexpectedEndOfInput(_), _))) => true,
+            _ => false,
+        };
+        if !agrees || nb_offset != offset + expected_len {
+            return Err(mismatch("nb::Decoder"));
+        }
+
+        offset += expected_len;
+    }
+
+    if values.next().is_some() {
+        return Err(Mismatch {
+                       path: "source::ValuesFrom",
+                       at: input.len() as u64,
+                   });
+    }
+
+    let mut out = vec![0u64; input.len()];
+    let mut count = 0;
+    let mut consumed = 0;
+    let batch = loop {
+        let end = cmp::min(count + chunk_size, out.len());
+        match decode_batch(&input[consumed..], &mut out[count..end]) {
+            Ok((0, _)) => break Ok((count, consumed)),
+            Ok((c, used)) => {
+                count += c;
+                consumed += used;
+            }
+            Err((e, at)) => break Err((e, consumed + at)),
+        }
+    };
+    let expected = match first_error {
+        Some(err) => Err(err),
+        None => Ok((valid.len(), input.len())),
+    };
+    if batch != expected || out[..valid.len()] != valid[..] {
+        return Err(Mismatch {
+                       path: "batch::decode_batch",
+                       at: first_error.map_or(input.len(), |(_, at)| at) as u64,
+                   });
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        let expected = match first_error {
+            Some(err) => Err(err),
+            None => Ok(valid),
+        };
+        if par_decode_all(input) != expected {
+            return Err(Mismatch {
+                           path: "par::par_decode_all",
+                           at: first_error.map_or(input.len(), |(_, at)| at) as u64,
+                       });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::{spread, spread_bytes};
+
+    #[test]
+    fn fixtures() {
+        assert_eq!(reference_encode(247), vec![247]);
+        assert_eq!(reference_encode(248), vec![248, 248]);
+        assert_eq!(reference_encode(256), vec![249, 1, 0]);
+        assert_eq!(reference_decode(&[249, 0, 255]), Err((DecodeError::NonCanonical(255), 3)));
+        assert_eq!(reference_decode(&[249, 1]), Err((DecodeError::UnexpectedEndOfInput(1), 2)));
+
+        assert_eq!(cross_check_decode(&[7, 248, 3, 249, 1, 0, 250, 1], 2), Ok(()));
+        let input = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 249, 1, 0, 248, 1];
+        assert_eq!(cross_check_decode(&input, 8), Ok(()));
+        assert_eq!(cross_check_decode(&input, 100), Ok(()));
+        assert_eq!(cross_check_encode_all(&[1, 2, 3, 4, 5, 6, 7, 8, 248, 0, 1 << 40]), Ok(()));
+        assert_eq!(first_difference(&[1, 2, 3], &[1, 2, 4, 5]), 2);
+        assert_eq!(first_difference(&[1, 2], &[1, 2, 4]), 2);
+
+        assert_eq!(format!("{}", Mismatch { path: "decode", at: 3 }),
+                   "decode disagrees with the reference at 3");
+    }
+
+    #[test]
+    fn test_decode_arbitrary() {
+        let bytes: Vec<u8> = (0..1000u32).map(|i| (i * 37 % 100) as u8).collect();
+        let mut input = spread_bytes(&bytes);
+        input.push(255);
+
+        // The data holds multi-byte, noncanonical and truncated encodings.
+        let mut kinds = [false; 3];
+        let mut offset = 0;
+        while offset < input.len() {
+            let (kind, len) = match reference_decode(&input[offset..]) {
+                Ok((n, len)) => (if n < 248 { None } else { Some(0) }, len),
+                Err((DecodeError::NonCanonical(_), len)) => (Some(1), len),
+                Err((_, len)) => (Some(2), len),
+            };
+            if let Some(kind) = kind {
+                kinds[kind] = true;
+            }
+            offset += len;
+        }
+        assert_eq!(kinds, [true, true, true]);
+
+        for chunk_size in [1, 7, 4096].iter() {
+            assert_eq!(cross_check_decode(&input, *chunk_size), Ok(()));
+        }
+    }
+
+    // Enough data for the parallel code to split it into several chunks.
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par() {
+        let values: Vec<u64> = (0..1 << 17).map(spread).collect();
+        assert_eq!(cross_check_encode_all(&values), Ok(()));
+
+        let mut input = Vec::new();
+        for n in values.iter() {
+            input.extend_from_slice(&reference_encode(*n));
+        }
+        assert_eq!(cross_check_decode(&input, 1 << 12), Ok(()));
+        let len = input.len();
+        input[len - 100] = 248;
+        input[len - 99] = 0;
+        assert_eq!(cross_check_decode(&input, 1 << 12), Ok(()));
+    }
+
+    quickcheck! {
+        fn test_encode(n: u64, shift: u8, chunk_size: usize) -> bool {
+            cross_check_encode(n.rotate_left(shift as u32), chunk_size).is_ok()
+        }
+
+        fn test_encode_all(values: Vec<u64>, small: Vec<u8>) -> bool {
+            // Runs of small values, so that the fast paths are taken.
+            let mut mixed = Vec::new();
+            for (n, b) in values.iter().zip(small.iter().cycle()) {
+                mixed.push(spread(*n));
+                mixed.extend((0..*b % 16).map(|i| (*b as u64 + i as u64) % 248));
+            }
+            cross_check_encode_all(&mixed).is_ok()
+        }
+
+        fn test_decode(input: Vec<u8>, chunk_size: usize) -> bool {
+            cross_check_decode(&spread_bytes(&input), chunk_size).is_ok()
+        }
+    }
+}
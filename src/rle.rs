@@ -0,0 +1,278 @@
+//! Run-length encoding of sequences with repeated values.
+//!
+//! A sequence is encoded as a series of items, each starting with a varu64 header `h`. If the
+//! least significant bit of `h` is set, the item is a run: `h >> 1` repetitions (at least two)
+//! of the value whose varu64 follows the header. Otherwise, the item is an escape for literals:
+//! `h >> 1` values (at least one) follow the header as varu64s, and are taken as they are.
+//!
+//! Every sequence has exactly one encoding: all repetitions of a value are in a single run,
+//! values that are not repeated are literals, and consecutive literals are in a single item.
+
+use super::{decode, encode_append, DecodeError};
+
+/// Run-length encodes the `values`, appending them to `out` and returning how many bytes have
+/// been written.
+pub fn rle_encode(values: &[u64], out: &mut Vec<u8>) -> usize {
+    let mut written = 0;
+    let mut literals_start = 0; // Index of the first value not yet written.
+    let mut i = 0;
+
+    while i < values.len() {
+        let run = values[i..].iter().take_while(|n| **n == values[i]).count();
+        if run >= 2 {
+            written += write_literals(&values[literals_start..i], out);
+            written += encode_append((run as u64) << 1 | 1, out);
+            written += encode_append(values[i], out);
+            literals_start = i + run;
+        }
+        i += run;
+    }
+
+    written + write_literals(&values[literals_start..], out)
+}
+
+// Write an escape for the literals, if there are any.
+fn write_literals(literals: &[u64], out: &mut Vec<u8>) -> usize {
+    if literals.is_empty() {
+        return 0;
+    }
+
+    let mut written = encode_append((literals.len() as u64) << 1, out);
+    for n in literals.iter() {
+        written += encode_append(*n, out);
+    }
+    written
+}
+
+/// Decode all values of a sequence encoded by `rle_encode` from the `input` buffer. Use
+/// `RleDecoder` to avoid allocating memory for long runs.
+///
+/// A ten byte input can encode a run of 2^63 - 1 values, so this must not be used on untrusted
+/// input. Use `rle_decode_with_max` instead.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts. See `RleDecoder` for the errors specific to the
+/// run-length encoding.
+pub fn rle_decode(input: &[u8]) -> Result<Vec<u64>, (DecodeError, usize)> {
+    collect(RleDecoder::new(input))
+}
+
+/// Decode all values of a sequence encoded by `rle_encode` from the `input` buffer, refusing to
+/// decode more than `max_values` values.
+///
+/// # Errors
+/// Errors are the same as those of `rle_decode`. A header that would take the number of values
+/// beyond `max_values` results in an `ExceedsLimit` error at the offset of the header, before
+/// any value of its item has been decoded.
+pub fn rle_decode_with_max(input: &[u8],
+                           max_values: u64)
+                           -> Result<Vec<u64>, (DecodeError, usize)> {
+    collect(RleDecoder::with_max(input, max_values))
+}
+
+fn collect(mut dec: RleDecoder) -> Result<Vec<u64>, (DecodeError, usize)> {
+    let mut values = Vec::new();
+    while let Some(n) = dec.next() {
+        values.push(n.map_err(|e| (e, dec.offset()))?);
+    }
+    Ok(values)
+}
+
+// What remains of the item the decoder is in.
+#[derive(Debug, Clone, Copy)]
+enum Item {
+    Run(u64, u64), // The value and how many repetitions remain.
+    Literals(u64), // How many literals remain.
+}
+
+/// An iterator over the values of a sequence encoded by `rle_encode`, expanding runs lazily.
+/// Created by `RleDecoder::new`.
+///
+/// A header whose count is too small, a run of the same value as its predecessor, a literal
+/// equal to its predecessor and an escape directly after another escape result in a
+/// `NonCanonical` error containing the header or value in question. A header that takes the
+/// number of values beyond the limit given to `with_max` results in an `ExceedsLimit` error.
+/// After yielding an error, the iterator ends.
+#[derive(Debug, Clone)]
+pub struct RleDecoder<'a> {
+    input: &'a [u8],
+    offset: usize, // Offset into `input` of the next encoding.
+    item: Item,
+    last: Option<u64>, // The last value yielded.
+    last_literal: bool, // Whether the last item was an escape.
+    total: u64, // How many values the items so far contain.
+    max: u64,
+    done: bool,
+}
+
+impl<'a> RleDecoder<'a> {
+    /// Create an iterator over the sequence encoded in the `input`.
+    pub fn new(input: &'a [u8]) -> RleDecoder<'a> {
+        RleDecoder::with_max(input, u64::MAX)
+    }
+
+    /// Create an iterator over the sequence encoded in the `input`, which yields an error
+    /// instead of more than `max_values` values.
+    pub fn with_max(input: &'a [u8], max_values: u64) -> RleDecoder<'a> {
+        RleDecoder {
+            input,
+            offset: 0,
+            item: Item::Literals(0),
+            last: None,
+            last_literal: false,
+            total: 0,
+            max: max_values,
+            done: false,
+        }
+    }
+
+    /// The offset into the input of the next encoding to decode. After an error, this is the
+    /// offset of the invalid encoding.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // Decode the next varu64, advancing past it if it is valid.
+    fn read(&mut self) -> Result<u64, DecodeError> {
+        let (n, tail) = decode(&self.input[self.offset..]).map_err(|(e, _)| e)?;
+        self.offset = self.input.len() - tail.len();
+        Ok(n)
+    }
+
+    // Decode the next value, checking that it differs from the last one.
+    fn read_value(&mut self) -> Result<u64, DecodeError> {
+        let start = self.offset;
+        let n = self.read()?;
+        if self.last == Some(n) {
+            self.offset = start;
+            return Err(DecodeError::NonCanonical(n));
+        }
+        self.last = Some(n);
+        Ok(n)
+    }
+
+    fn step(&mut self) -> Option<Result<u64, DecodeError>> {
+        loop {
+            match self.item {
+                Item::Run(n, remaining) if remaining > 0 => {
+                    self.item = Item::Run(n, remaining - 1);
+                    return Some(Ok(n));
+                }
+                Item::Literals(remaining) if remaining > 0 => {
+                    self.item = Item::Literals(remaining - 1);
+                    return Some(self.read_value());
+                }
+                _ => {}
+            }
+
+            if self.offset == self.input.len() {
+                return None;
+            }
+
+            let start = self.offset;
+            let header = match self.read() {
+                Ok(header) => header,
+                Err(e) => return Some(Err(e)),
+            };
+            let count = header >> 1;
+            let total = self.total.saturating_add(count);
+            if total > self.max {
+                self.offset = start;
+                return Some(Err(DecodeError::ExceedsLimit {
+                                    limit: self.max,
+                                    actual: total,
+                                }));
+            }
+            if header & 1 == 1 && count >= 2 {
+                let n = match self.read_value() {
+                    Ok(n) => n,
+                    Err(e) => return Some(Err(e)),
+                };
+                self.item = Item::Run(n, count);
+                self.last_literal = false;
+                self.total = total;
+            } else if header & 1 == 0 && count >= 1 && !self.last_literal {
+                self.item = Item::Literals(count);
+                self.last_literal = true;
+                self.total = total;
+            } else {
+                self.offset = start;
+                return Some(Err(DecodeError::NonCanonical(header)));
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for RleDecoder<'a> {
+    type Item = Result<u64, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.step();
+        if let Some(Err(_)) = result {
+            self.done = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let values = [7, 7, 7, 1, 2, 300, 300, 3];
+        let mut out = Vec::new();
+        assert_eq!(rle_encode(&values, &mut out), 11);
+        assert_eq!(out, vec![7, 7, 4, 1, 2, 5, 249, 1, 44, 2, 3]);
+        assert_eq!(rle_decode(&out), Ok(values.to_vec()));
+
+        let mut dec = RleDecoder::new(&[255, 255, 255, 255, 255, 255, 255, 255, 255, 9]);
+        assert_eq!(dec.by_ref().take(3).collect::<Vec<_>>(), vec![Ok(9), Ok(9), Ok(9)]);
+        assert_eq!(dec.offset(), 10);
+
+        assert_eq!(rle_decode(&[]), Ok(vec![]));
+        assert_eq!(rle_decode(&[2, 1, 2, 2]), Err((NonCanonical(2), 2)));
+        assert_eq!(rle_decode(&[4, 1, 1]), Err((NonCanonical(1), 2)));
+        assert_eq!(rle_decode(&[5, 1, 2, 1]), Err((NonCanonical(1), 3)));
+        assert_eq!(rle_decode(&[3, 1]), Err((NonCanonical(3), 0)));
+        assert_eq!(rle_decode(&[0]), Err((NonCanonical(0), 0)));
+        assert_eq!(rle_decode(&[4, 1]), Err((UnexpectedEndOfInput(1), 2)));
+        assert_eq!(rle_decode(&[248, 5]), Err((NonCanonical(5), 0)));
+    }
+
+    #[test]
+    fn test_with_max() {
+        let input = [255, 255, 255, 255, 255, 255, 255, 255, 255, 9];
+        assert_eq!(rle_decode_with_max(&input, 1 << 20),
+                   Err((ExceedsLimit {
+                            limit: 1 << 20,
+                            actual: u64::MAX >> 1,
+                        },
+                        0)));
+
+        let input = [7, 7, 4, 1, 2, 5, 249, 1, 44, 2, 3];
+        assert_eq!(rle_decode_with_max(&input, 8), Ok(vec![7, 7, 7, 1, 2, 300, 300, 3]));
+        assert_eq!(rle_decode_with_max(&input, 7),
+                   Err((ExceedsLimit { limit: 7, actual: 8 }, 9)));
+        assert_eq!(rle_decode_with_max(&input, 4),
+                   Err((ExceedsLimit { limit: 4, actual: 5 }, 2)));
+    }
+
+    quickcheck! {
+        fn test_roundtrip(values: Vec<u8>) -> bool {
+            // Few distinct values, so that there are runs.
+            let values: Vec<u64> = values.iter().map(|n| (*n % 4) as u64 * 1000).collect();
+            let mut out = Vec::new();
+            rle_encode(&values, &mut out) == out.len() &&
+            rle_decode_with_max(&out, values.len() as u64) == Ok(values.clone()) &&
+            rle_decode(&out) == Ok(values)
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Compact serde representations of integers and integer sequences, available with the `serde`
+//! feature.
+
+use std::{cmp, fmt};
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor, SeqAccess};
+
+use super::{encode_append, encode_owned, decode, decode_exact, VarU64};
+
+// The most bytes to reserve based on the size hint of a sequence.
+const MAX_PREALLOC: usize = 4096;
+
+/// A wrapper around a `Vec<u64>` that serializes as a single byte string containing the
+/// concatenated varu64 encodings of the values.
+///
+/// Deserialization accepts byte strings as well as sequences of bytes (which is how formats
+/// like JSON represent byte strings), and rejects invalid encodings.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VaruSeq(pub Vec<u64>);
+
+impl Serialize for VaruSeq {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bytes = Vec::new();
+        for n in self.0.iter() {
+            encode_append(*n, &mut bytes);
+        }
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for VaruSeq {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<VaruSeq, D::Error> {
+        deserializer.deserialize_byte_buf(VaruSeqVisitor)
+    }
+}
+
+struct VaruSeqVisitor;
+
+impl<'de> Visitor<'de> for VaruSeqVisitor {
+    type Value = VaruSeq;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a byte string of concatenated varu64s")
+    }
+
+    fn visit_bytes<E: de::Error>(self, mut bytes: &[u8]) -> Result<VaruSeq, E> {
+        let mut values = Vec::new();
+        while !bytes.is_empty() {
+            match decode(bytes) {
+                Ok((n, tail)) => {
+                    values.push(n);
+                    bytes = tail;
+                }
+                Err((e, _)) => return Err(E::custom(e)),
+            }
+        }
+        Ok(VaruSeq(values))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<VaruSeq, A::Error> {
+        // The size hint comes from the input, so it is not trusted to allocate up front.
+        let mut bytes = Vec::with_capacity(cmp::min(seq.size_hint().unwrap_or(0), MAX_PREALLOC));
+        while let Some(b) = seq.next_element()? {
+            bytes.push(b);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+impl From<Vec<u64>> for VaruSeq {
+    fn from(values: Vec<u64>) -> VaruSeq {
+        VaruSeq(values)
+    }
+}
+
+impl From<VaruSeq> for Vec<u64> {
+    fn from(seq: VaruSeq) -> Vec<u64> {
+        seq.0
+    }
+}
+
+/// Serializes as a byte string containing the canonical encoding of the number.
+///
+/// Deserialization accepts byte strings as well as sequences of bytes, and rejects invalid
+/// encodings and trailing bytes.
+impl Serialize for VarU64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&encode_owned(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for VarU64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<VarU64, D::Error> {
+        deserializer.deserialize_bytes(VarU64Visitor)
+    }
+}
+
+struct VarU64Visitor;
+
+impl<'de> Visitor<'de> for VarU64Visitor {
+    type Value = VarU64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a byte string containing a varu64")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<VarU64, E> {
+        decode_exact(bytes).map(VarU64).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<VarU64, A::Error> {
+        let mut bytes = Vec::with_capacity(9);
+        while let Some(b) = seq.next_element()? {
+            if bytes.len() == 9 {
+                return Err(de::Error::invalid_length(10, &self));
+            }
+            bytes.push(b);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_test::{assert_tokens, assert_de_tokens, assert_de_tokens_error, Token};
+
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        let seq = VaruSeq(vec![1, 300]);
+        assert_tokens(&seq, &[Token::Bytes(&[1, 249, 1, 44])]);
+        assert_de_tokens(&seq, &[Token::ByteBuf(&[1, 249, 1, 44])]);
+        assert_de_tokens(&seq,
+                         &[Token::Seq { len: Some(4) },
+                           Token::U8(1),
+                           Token::U8(249),
+                           Token::U8(1),
+                           Token::U8(44),
+                           Token::SeqEnd]);
+        assert_de_tokens(&VaruSeq(vec![7]),
+                         &[Token::Seq { len: Some(usize::MAX) }, Token::U8(7), Token::SeqEnd]);
+
+        assert_de_tokens_error::<VaruSeq>(&[Token::Bytes(&[248, 1])],
+                                          "Invalid varu64: NonCanonical encoding of 1");
+        assert_de_tokens_error::<VaruSeq>(&[Token::Bytes(&[249, 1])],
+                                          "Invalid varu64: Not enough input bytes, 1 more \
+                                           needed");
+
+        assert_tokens(&VarU64(300), &[Token::Bytes(&[249, 1, 44])]);
+        assert_de_tokens(&VarU64(300),
+                         &[Token::Seq { len: None },
+                           Token::U8(249),
+                           Token::U8(1),
+                           Token::U8(44),
+                           Token::SeqEnd]);
+        assert_de_tokens_error::<VarU64>(&[Token::Bytes(&[7, 0])],
+                                         "Invalid varu64: Trailing bytes after the encoding");
+    }
+}
@@ -0,0 +1,251 @@
+//! A Simple8b-style word-packed block format for dense small values.
+//!
+//! This is a distinct wire format, not varu64. A block consists of a varu64 holding the number
+//! of words, followed by that many big-endian 64 bit words. The four most significant bits of
+//! each word are a selector, the remaining 60 bits hold as many equally-sized values as the
+//! selector indicates:
+//!
+//! | selector | values | bits per value |
+//! |----------|--------|----------------|
+//! | 0 | 240 | 0 |
+//! | 1 | 120 | 0 |
+//! | 2 | 60 | 1 |
+//! | 3 | 30 | 2 |
+//! | 4 | 20 | 3 |
+//! | 5 | 15 | 4 |
+//! | 6 | 12 | 5 |
+//! | 7 | 10 | 6 |
+//! | 8 | 8 | 7 |
+//! | 9 | 7 | 8 |
+//! | 10 | 6 | 10 |
+//! | 11 | 5 | 12 |
+//! | 12 | 4 | 15 |
+//! | 13 | 3 | 20 |
+//! | 14 | 2 | 30 |
+//! | 15 | 1 | 60 |
+//!
+//! Values are stored starting at the least significant bits of the payload. Only values below
+//! 2^60 can be encoded. Unlike varu64, the format is not canonical: the decoder accepts any
+//! valid packing, the encoder greedily picks the densest selector.
+
+use std::{fmt, error};
+use std::convert::TryFrom;
+
+use super::DecodeError;
+
+// (number of values, bits per value) for each selector.
+static SELECTORS: [(usize, u32); 16] = [(240, 0),
+                                        (120, 0),
+                                        (60, 1),
+                                        (30, 2),
+                                        (20, 3),
+                                        (15, 4),
+                                        (12, 5),
+                                        (10, 6),
+                                        (8, 7),
+                                        (7, 8),
+                                        (6, 10),
+                                        (5, 12),
+                                        (4, 15),
+                                        (3, 20),
+                                        (2, 30),
+                                        (1, 60)];
+
+/// The largest value that can be encoded.
+pub const MAX_VALUE: u64 = (1 << 60) - 1;
+
+/// Everything that can go wrong when encoding a block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EncodeError {
+    /// The value is greater than `MAX_VALUE`. Contains the value.
+    ValueTooLarge(u64),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        match self {
+            EncodeError::ValueTooLarge(n) => {
+                write!(f, "Invalid simple8b value: {} does not fit into 60 bits", n)
+            }
+        }
+    }
+}
+
+impl error::Error for EncodeError {}
+
+/// Encodes the `values` as a block, appending it to `out` and returning how many bytes have
+/// been written.
+///
+/// If an error is returned, nothing has been appended to `out`.
+pub fn encode(values: &[u64], out: &mut Vec<u8>) -> Result<usize, EncodeError> {
+    if let Some(n) = values.iter().find(|n| **n > MAX_VALUE) {
+        return Err(EncodeError::ValueTooLarge(*n));
+    }
+
+    let mut words = Vec::new();
+    let mut remaining = values;
+    while !remaining.is_empty() {
+        let (word, packed) = pack(remaining);
+        words.push(word);
+        remaining = &remaining[packed..];
+    }
+
+    let header_len = super::encode_append(words.len() as u64, out);
+    for word in words.iter() {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    Ok(header_len + 8 * words.len())
+}
+
+// Pack as many values as possible into a single word, returning the word and how many values
+// it holds. `values` must be non-empty and all values must be at most `MAX_VALUE`.
+fn pack(values: &[u64]) -> (u64, usize) {
+    for (selector, &(count, bits)) in SELECTORS.iter().enumerate() {
+        if count > values.len() || values[..count].iter().any(|n| (n >> bits) != 0) {
+            continue;
+        }
+
+        let mut word = (selector as u64) << 60;
+        for (i, n) in values[..count].iter().enumerate() {
+            word |= n << (i as u32 * bits);
+        }
+        return (word, count);
+    }
+
+    unreachable!() // selector 15 fits any single value up to MAX_VALUE
+}
+
+// Unpack the `i`-th value of a word.
+fn unpack(word: u64, i: usize) -> u64 {
+    let (_, bits) = SELECTORS[(word >> 60) as usize];
+    if bits == 0 {
+        0
+    } else {
+        (word >> (i as u32 * bits)) & ((1 << bits) - 1)
+    }
+}
+
+/// Decode a block from the `input` buffer, returning the values and the remaining bytes.
+///
+/// # Errors
+/// Errors are reported like for `varu64::decode`. Since every selector is valid, the only
+/// errors are a noncanonical word count and running out of input.
+pub fn decode(input: &[u8]) -> Result<(Vec<u64>, &[u8]), (DecodeError, &[u8])> {
+    let (iter, tail) = iter(input)?;
+    Ok((iter.collect(), tail))
+}
+
+/// Parse the header of a block from the `input` buffer, returning an iterator over the values
+/// and the bytes following the block.
+///
+/// The full block is checked to be present before returning, so iteration itself can not fail.
+pub fn iter(input: &[u8]) -> Result<(Iter<'_>, &[u8]), (DecodeError, &[u8])> {
+    let (word_count, words) = super::decode(input)?;
+
+    if (words.len() as u64) / 8 < word_count {
+        let needed = usize::try_from(word_count.saturating_mul(8) - words.len() as u64)
+            .unwrap_or(usize::MAX);
+        return Err((DecodeError::UnexpectedEndOfInput(needed), &words[words.len()..]));
+    }
+
+    let len = word_count as usize * 8;
+    Ok((Iter {
+            words: &words[..len],
+            index: 0,
+        },
+        &words[len..]))
+}
+
+/// An iterator over the values of a block, created by `iter`.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    words: &'a [u8], // The words that have not been fully yielded yet.
+    index: usize, // Index of the next value within the first word.
+}
+
+impl<'a> Iter<'a> {
+    fn first_word(&self) -> u64 {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&self.words[..8]);
+        u64::from_be_bytes(word)
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.words.is_empty() {
+            return None;
+        }
+
+        let word = self.first_word();
+        let value = unpack(word, self.index);
+
+        self.index += 1;
+        if self.index == SELECTORS[(word >> 60) as usize].0 {
+            self.words = &self.words[8..];
+            self.index = 0;
+        }
+
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::spread;
+
+    #[test]
+    fn fixtures() {
+        let mut out = Vec::new();
+        assert_eq!(encode(&[], &mut out), Ok(1));
+        assert_eq!(out, vec![0]);
+
+        out.clear();
+        assert_eq!(encode(&[MAX_VALUE], &mut out), Ok(9));
+        assert_eq!(out, vec![1, 255, 255, 255, 255, 255, 255, 255, 255]);
+
+        out.clear();
+        assert_eq!(encode(&[1, 2], &mut out), Ok(9));
+        assert_eq!(out, vec![1, 0xe0, 0, 0, 0, 0x80, 0, 0, 1]);
+
+        out.clear();
+        assert_eq!(encode(&[0; 240], &mut out), Ok(9));
+        assert_eq!(out, vec![1, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        out.clear();
+        assert_eq!(encode(&[3, MAX_VALUE + 1], &mut out),
+                   Err(EncodeError::ValueTooLarge(MAX_VALUE + 1)));
+        assert_eq!(out, vec![]);
+
+        assert_eq!(decode(&[1, 0xe0, 0, 0, 0, 0x80, 0, 0, 1, 42]).unwrap(),
+                   (vec![1, 2], &[42][..]));
+        assert_eq!(decode(&[1, 0xe0, 0, 0]).unwrap_err(),
+                   (DecodeError::UnexpectedEndOfInput(5), &[][..]));
+        assert_eq!(decode(&[248, 1]).unwrap_err(),
+                   (DecodeError::NonCanonical(1), &[][..]));
+    }
+
+    quickcheck! {
+        fn roundtrip(values: Vec<u64>, shift: u8) -> bool {
+            // Spread out values below 2^60, of a bit width depending on the shift.
+            let values: Vec<u64> = values
+                .iter()
+                .map(|n| spread(*n) >> (4 + (shift % 60)))
+                .collect();
+
+            let mut out = vec![42];
+            let written = encode(&values, &mut out).unwrap();
+            assert_eq!(written, out.len() - 1);
+            out.push(43);
+
+            let (decoded, tail) = decode(&out[1..]).unwrap();
+            assert_eq!(decoded, values);
+            assert_eq!(tail, &[43][..]);
+
+            true
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Encoding into stack-allocated [`SmallVec`](https://docs.rs/smallvec)s, available with the
+//! `smallvec` feature.
+
+use smallvec::{Array, SmallVec};
+
+use super::encode;
+
+/// Encodes `n` into a `SmallVec` that never spills onto the heap.
+pub fn encode_to_smallvec(n: u64) -> SmallVec<[u8; 9]> {
+    let mut out = SmallVec::from_buf([0u8; 9]);
+    let len = encode(n, &mut out[..]);
+    out.truncate(len);
+    out
+}
+
+/// Encodes the length of `bytes` followed by the `bytes` themselves into a `SmallVec`, which
+/// only spills onto the heap if the encoding exceeds the inline capacity of `A`.
+pub fn encode_length_prefixed_to_smallvec<A: Array<Item = u8>>(bytes: &[u8]) -> SmallVec<A> {
+    let mut out = SmallVec::new();
+    out.extend_from_slice(&encode_to_smallvec(bytes.len() as u64));
+    out.extend_from_slice(bytes);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        assert_eq!(&encode_to_smallvec(0)[..], &[0][..]);
+        assert_eq!(&encode_to_smallvec(256)[..], &[249, 1, 0][..]);
+        assert_eq!(&encode_to_smallvec(u64::MAX)[..], &[255; 9][..]);
+
+        let lv: SmallVec<[u8; 4]> = encode_length_prefixed_to_smallvec(&[7, 8]);
+        assert_eq!(&lv[..], &[2, 7, 8][..]);
+        assert!(!lv.spilled());
+
+        let lv: SmallVec<[u8; 4]> = encode_length_prefixed_to_smallvec(&[42; 300]);
+        assert_eq!(&lv[..3], &[249, 1, 44][..]);
+        assert_eq!(lv.len(), 303);
+    }
+}
@@ -0,0 +1,257 @@
+//! A single input abstraction for decoding from slices, lists of chunks, ring buffers and
+//! readers.
+//!
+//! Implement `Source` for a new input shape to make it usable with `decode_from`, `ValuesFrom`
+//! and `validate::validate_source`, rather than writing a bespoke decode function for it. The
+//! reader-based parts of the `hash`, `column` and `mux` modules are built on `ReadSource`.
+//!
+//! Some functions on slices stay slice-based, since they rely on having the whole buffer at
+//! hand, and a source only yields one byte at a time:
+//!
+//! - the iterators of the `iter` module iterate from both ends,
+//! - `decode_all` and `decode_all_into` scan the tag bytes up front to reserve capacity,
+//! - `decode` and the functions built on it, such as `hash::hash_values`, load the payload of an
+//!   encoding as a whole rather than byte by byte.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::{error, fmt, io};
+
+use super::{decode, decoded_len, DecodeError};
+
+/// Something that produces bytes one at a time.
+pub trait Source {
+    /// The error type of the underlying input, `Infallible` for in-memory sources.
+    type Error;
+
+    /// Return the next byte, or `None` if the input has ended.
+    fn next_byte(&mut self) -> Result<Option<u8>, Self::Error>;
+}
+
+/// Reading a slice consumes it from the front.
+impl Source for &[u8] {
+    type Error = Infallible;
+
+    fn next_byte(&mut self) -> Result<Option<u8>, Infallible> {
+        match self.split_first() {
+            Some((b, tail)) => {
+                *self = tail;
+                Ok(Some(*b))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reading a ring buffer pops bytes from its front.
+impl Source for VecDeque<u8> {
+    type Error = Infallible;
+
+    fn next_byte(&mut self) -> Result<Option<u8>, Infallible> {
+        Ok(self.pop_front())
+    }
+}
+
+/// A list of chunks, read as if they were concatenated.
+#[derive(Debug, Clone)]
+pub struct ChunksSource<'a> {
+    chunks: &'a [&'a [u8]],
+    offset: usize, // Offset into the first chunk.
+}
+
+impl<'a> ChunksSource<'a> {
+    /// Create a source reading the `chunks` in order.
+    pub fn new(chunks: &'a [&'a [u8]]) -> ChunksSource<'a> {
+        ChunksSource { chunks, offset: 0 }
+    }
+}
+
+impl<'a> Source for ChunksSource<'a> {
+    type Error = Infallible;
+
+    fn next_byte(&mut self) -> Result<Option<u8>, Infallible> {
+        while let Some((chunk, tail)) = self.chunks.split_first() {
+            if self.offset < chunk.len() {
+                self.offset += 1;
+                return Ok(Some(chunk[self.offset - 1]));
+            }
+            self.chunks = tail;
+            self.offset = 0;
+        }
+        Ok(None)
+    }
+}
+
+/// A reader, read one byte at a time. Wrap unbuffered readers in a `BufReader`.
+///
+/// Interrupted reads are retried as described in the [crate docs](../index.html#io).
+#[derive(Debug)]
+pub struct ReadSource<R>(pub R);
+
+impl<R: io::Read> Source for ReadSource<R> {
+    type Error = io::Error;
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0u8; 1];
+        loop {
+            match self.0.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buf[0])),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Everything that can go wrong when decoding from a `Source`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceError<E> {
+    /// The data is not a valid varu64.
+    Decode(DecodeError),
+    /// The source reported an error.
+    Source(E),
+}
+
+impl<E: fmt::Display> fmt::Display for SourceError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        match self {
+            SourceError::Decode(e) => e.fmt(f),
+            SourceError::Source(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> error::Error for SourceError<E> {}
+
+impl<E> From<DecodeError> for SourceError<E> {
+    fn from(e: DecodeError) -> SourceError<E> {
+        SourceError::Decode(e)
+    }
+}
+
+impl From<SourceError<io::Error>> for io::Error {
+    fn from(e: SourceError<io::Error>) -> io::Error {
+        match e {
+            SourceError::Decode(e) => e.into(),
+            SourceError::Source(e) => e,
+        }
+    }
+}
+
+/// Decode the next varu64 from the source. Returns `None` if the source ends before the first
+/// byte of an encoding.
+///
+/// # Errors
+/// The full encoding is consumed before reporting a `NonCanonical` error, so decoding can
+/// continue after it. If the source ends within an encoding, an `UnexpectedEndOfInput` error is
+/// returned.
+pub fn decode_from<S: Source + ?Sized>(s: &mut S) -> Result<Option<u64>, SourceError<S::Error>> {
+    let mut buf = [0u8; 9];
+    buf[0] = match s.next_byte().map_err(SourceError::Source)? {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+
+    let len = decoded_len(buf[0]);
+    for (i, slot) in buf[1..len].iter_mut().enumerate() {
+        *slot = match s.next_byte().map_err(SourceError::Source)? {
+            Some(b) => b,
+            None => return Err(DecodeError::UnexpectedEndOfInput(len - 1 - i).into()),
+        };
+    }
+
+    match decode(&buf[..len]) {
+        Ok((n, _)) => Ok(Some(n)),
+        Err((e, _)) => Err(e.into()),
+    }
+}
+
+/// An iterator over the values of a source. Created by `ValuesFrom::new`.
+///
+/// After yielding an error, the iterator continues with the next encoding.
+#[derive(Debug)]
+pub struct ValuesFrom<S>(S);
+
+impl<S: Source> ValuesFrom<S> {
+    /// Create an iterator over the values of the source.
+    pub fn new(s: S) -> ValuesFrom<S> {
+        ValuesFrom(s)
+    }
+}
+
+impl<S: Source> Iterator for ValuesFrom<S> {
+    type Item = Result<u64, SourceError<S::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match decode_from(&mut self.0) {
+            Ok(Some(n)) => Some(Ok(n)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use super::super::encode;
+    use super::super::tests::{FlakyReader, spread_all};
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let data = [7, 248, 3, 249, 1];
+        let values: Vec<_> = ValuesFrom::new(&data[..]).collect();
+        assert_eq!(values,
+                   vec![Ok(7),
+                        Err(SourceError::Decode(NonCanonical(3))),
+                        Err(SourceError::Decode(UnexpectedEndOfInput(1)))]);
+
+        let chunks = [&[249][..], &[][..], &[1][..], &[0, 5][..]];
+        let values: Vec<_> = ValuesFrom::new(ChunksSource::new(&chunks)).collect();
+        assert_eq!(values, vec![Ok(256), Ok(5)]);
+
+        let mut deque: VecDeque<u8> = vec![248, 255, 1].into_iter().collect();
+        assert_eq!(decode_from(&mut deque), Ok(Some(255)));
+        assert_eq!(deque.len(), 1);
+
+        let mut r = ReadSource(&[249, 1][..]);
+        assert_eq!(io::Error::from(decode_from(&mut r).unwrap_err()).kind(),
+                   io::ErrorKind::UnexpectedEof);
+    }
+
+    quickcheck! {
+        fn test_sources(values: Vec<u64>, split: usize) -> bool {
+            let values = spread_all(&values);
+            let mut data = Vec::new();
+            for n in values.iter() {
+                let mut buf = [0u8; 9];
+                let len = encode(*n, &mut buf[..]);
+                data.extend_from_slice(&buf[..len]);
+            }
+            let split = if data.is_empty() { 0 } else { split % data.len() };
+            let chunks = [&data[..split], &data[split..]];
+
+            let from_slice: Vec<u64> = ValuesFrom::new(&data[..]).map(|n| n.unwrap()).collect();
+            let from_chunks: Vec<u64> = ValuesFrom::new(ChunksSource::new(&chunks))
+                .map(|n| n.unwrap())
+                .collect();
+            let from_deque: Vec<u64> = ValuesFrom::new(data.iter().cloned()
+                                                           .collect::<VecDeque<u8>>())
+                .map(|n| n.unwrap())
+                .collect();
+            let from_reader: Vec<u64> = ValuesFrom::new(ReadSource(&data[..]))
+                .map(|n| n.unwrap())
+                .collect();
+            let from_flaky: Vec<u64> = ValuesFrom::new(ReadSource(FlakyReader::new(&data)))
+                .map(|n| n.unwrap())
+                .collect();
+
+            from_slice == values && from_chunks == values && from_deque == values &&
+            from_reader == values && from_flaky == values
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! The constants of the varu64 format.
+
+/// The largest value encoded as a single byte, without a tag.
+pub const MAX_SINGLE_BYTE: u8 = 247;
+
+/// The tag bytes, `TAGS[k - 1]` indicates an encoding with `k` payload bytes.
+pub const TAGS: [u8; 8] = [248, 249, 250, 251, 252, 253, 254, 255];
+
+/// `THRESHOLDS[k - 1]` is the smallest value whose canonical encoding has `k` payload bytes.
+/// Encoding a smaller value with `k` payload bytes is noncanonical.
+pub const THRESHOLDS: [u64; 8] = [248,
+                                  1 << 8,
+                                  1 << 16,
+                                  1 << 24,
+                                  1 << 32,
+                                  1 << 40,
+                                  1 << 48,
+                                  1 << 56];
+
+/// The length of the longest encoding, a tag byte followed by eight payload bytes.
+pub const MAX_ENCODING_LENGTH: usize = 9;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{decoded_len, encode, encoding_length};
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(encoding_length(MAX_SINGLE_BYTE as u64), 1);
+        assert_eq!(encoding_length(u64::MAX), MAX_ENCODING_LENGTH);
+
+        for k in 1..9 {
+            let mut buf = [0u8; MAX_ENCODING_LENGTH];
+            assert_eq!(encode(THRESHOLDS[k - 1], &mut buf[..]), k + 1);
+            assert_eq!(buf[0], TAGS[k - 1]);
+            assert_eq!(decoded_len(TAGS[k - 1]), k + 1);
+            assert_eq!(encoding_length(THRESHOLDS[k - 1] - 1), k);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Conversion between human-readable lists of numbers and encoded varu64 streams.
+
+use std::io::{self, BufRead, Read, Write};
+
+use super::{encode, nb};
+
+// The length of the longest valid token, `u64::MAX` in decimal.
+const MAX_TOKEN_LEN: usize = 20;
+
+/// Parse whitespace-separated numbers from `r`, writing their encodings to `w`. Returns how many
+/// numbers have been converted.
+///
+/// Numbers are written in decimal, or in hexadecimal with a `0x` prefix, without a sign.
+///
+/// `r` is buffered internally, but every encoding is written to `w` with a separate call, so
+/// unbuffered writers such as files or sockets should be wrapped in a `BufWriter`.
+///
+/// # Errors
+/// A token that is not a valid number results in an error of kind `InvalidData`. Tokens longer
+/// than 20 bytes are rejected as soon as they exceed that length.
+pub fn encode_text<R: Read, W: Write>(r: R, mut w: W) -> io::Result<usize> {
+    let mut r = io::BufReader::new(r);
+    let mut token = Vec::new();
+    let mut count = 0;
+
+    loop {
+        let (consumed, done) = {
+            let buf = r.fill_buf()?;
+            if buf.is_empty() {
+                (0, true)
+            } else {
+                for b in buf.iter() {
+                    if b.is_ascii_whitespace() {
+                        if !token.is_empty() {
+                            write_token(&token, &mut w)?;
+                            token.clear();
+                            count += 1;
+                        }
+                    } else if token.len() == MAX_TOKEN_LEN {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                  format!("Token too long: {}...",
+                                                          String::from_utf8_lossy(&token))));
+                    } else {
+                        token.push(*b);
+                    }
+                }
+                (buf.len(), false)
+            }
+        };
+
+        if done {
+            break;
+        }
+        r.consume(consumed);
+    }
+
+    if !token.is_empty() {
+        write_token(&token, &mut w)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+// Parse a single number and write its encoding.
+fn write_token<W: Write>(token: &[u8], w: &mut W) -> io::Result<()> {
+    let n = parse_number(token).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData,
+                       format!("Invalid number: {}", String::from_utf8_lossy(token)))
+    })?;
+
+    let mut buf = [0u8; 9];
+    let len = encode(n, &mut buf[..]);
+    w.write_all(&buf[..len])
+}
+
+// Parse a number, rejecting the leading sign that `from_str_radix` accepts.
+fn parse_number(token: &[u8]) -> Option<u64> {
+    let s = ::std::str::from_utf8(token).ok()?;
+    let (digits, radix) = if s.starts_with("0x") || s.starts_with("0X") {
+        (&s[2..], 16)
+    } else {
+        (s, 10)
+    };
+
+    if digits.starts_with('+') {
+        return None;
+    }
+    u64::from_str_radix(digits, radix).ok()
+}
+
+/// Decode the varu64s from `r`, writing them to `w` in decimal, one per line. Returns how many
+/// numbers have been converted.
+///
+/// Every line is written to `w` with a separate call, so unbuffered writers such as files or
+/// sockets should be wrapped in a `BufWriter`.
+///
+/// # Errors
+/// A noncanonical encoding results in an error of kind `InvalidData`, an encoding that is cut
+/// off by the end of `r` in an error of kind `UnexpectedEof`.
+pub fn decode_text<R: Read, W: Write>(mut r: R, mut w: W) -> io::Result<usize> {
+    let mut dec = nb::Decoder::new();
+    let mut buf = [0u8; 4096];
+    let mut partial = false; // Whether the decoder is within an encoding.
+    let mut count = 0;
+
+    loop {
+        let read = match r.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        let mut chunk = &buf[..read];
+        while !chunk.is_empty() {
+            let (consumed, result) = dec.decode(chunk);
+            chunk = &chunk[consumed..];
+
+            match result {
+                None => partial = true,
+                Some(Ok(n)) => {
+                    partial = false;
+                    writeln!(w, "{}", n)?;
+                    count += 1;
+                }
+                Some(Err(nb::DecodeError::NonCanonical)) => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              "Invalid varu64: NonCanonical encoding"));
+                }
+            }
+        }
+    }
+
+    if partial {
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                           "Invalid varu64: Not enough input bytes"))
+    } else {
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let mut out = Vec::new();
+        assert_eq!(encode_text(&b" 7\n248 0x100\t\n0XFF"[..], &mut out).unwrap(), 4);
+        assert_eq!(out, vec![7, 248, 248, 249, 1, 0, 248, 255]);
+
+        let mut text = Vec::new();
+        assert_eq!(decode_text(&out[..], &mut text).unwrap(), 4);
+        assert_eq!(text, b"7\n248\n256\n255\n".to_vec());
+
+        assert_eq!(encode_text(&b"1 x2"[..], &mut Vec::new()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(encode_text(&b"+5"[..], &mut Vec::new()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(encode_text(&b"0x+ff"[..], &mut Vec::new()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(encode_text(&b"000000000000000000001"[..], &mut Vec::new())
+                       .unwrap_err()
+                       .kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(encode_text(&b"18446744073709551615"[..], &mut out).unwrap(), 1);
+        assert_eq!(encode_text(&b"18446744073709551616"[..], &mut Vec::new())
+                       .unwrap_err()
+                       .kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(decode_text(&[1, 248, 3][..], &mut Vec::new()).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(decode_text(&[1, 249, 3][..], &mut Vec::new()).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+    }
+
+    quickcheck! {
+        fn test_roundtrip(values: Vec<u64>) -> bool {
+            let text: Vec<String> = spread_all(&values).iter().map(|n| n.to_string()).collect();
+
+            let mut encoded = Vec::new();
+            encode_text(text.join(" ").as_bytes(), &mut encoded).unwrap();
+            let mut decoded = Vec::new();
+            decode_text(&encoded[..], &mut decoded).unwrap();
+
+            String::from_utf8(decoded).unwrap().lines().collect::<Vec<_>>() == text
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Delta-of-delta encoding of timestamps.
+//!
+//! A sequence is encoded as the varu64 of its first value, the zigzag encoded (see
+//! `biased::encode_i64`) difference between the first two values, and then for every further
+//! value the zigzag encoded change of the difference to its predecessor. A sequence with a
+//! regular interval thus takes up one byte per value after the first two, and small jitter
+//! adds little to this.
+//!
+//! Differences are computed with wrapping arithmetic, so every sequence of `u64`s can be
+//! encoded, whether it is sorted or not.
+
+use super::biased::{unzigzag, zigzag};
+use super::{decode, encode_append, DecodeError};
+
+/// Encodes the `timestamps`, appending them to `out` and returning how many bytes have been
+/// written.
+pub fn encode_timestamps(timestamps: &[u64], out: &mut Vec<u8>) -> usize {
+    let mut written = 0;
+    let mut previous = 0u64;
+    let mut previous_delta = 0u64;
+
+    for (i, t) in timestamps.iter().enumerate() {
+        written += match i {
+            0 => encode_append(*t, out),
+            _ => {
+                let delta = t.wrapping_sub(previous);
+                let change = delta.wrapping_sub(previous_delta) as i64;
+                previous_delta = delta;
+                encode_append(zigzag(change), out)
+            }
+        };
+        previous = *t;
+    }
+
+    written
+}
+
+/// Decode all timestamps of a sequence encoded by `encode_timestamps` from the `input` buffer.
+///
+/// # Errors
+/// On error, this returns the error of the first invalid encoding, and the offset into the
+/// `input` at which that encoding starts.
+pub fn decode_timestamps(input: &[u8]) -> Result<Vec<u64>, (DecodeError, usize)> {
+    let mut timestamps = Vec::new();
+    let mut previous = 0u64;
+    let mut previous_delta = 0u64;
+    let mut remaining = input;
+
+    while !remaining.is_empty() {
+        let offset = input.len() - remaining.len();
+        let (n, tail) = decode(remaining).map_err(|(e, _)| (e, offset))?;
+        if timestamps.is_empty() {
+            previous = n;
+        } else {
+            previous_delta = previous_delta.wrapping_add(unzigzag(n) as u64);
+            previous = previous.wrapping_add(previous_delta);
+        }
+        timestamps.push(previous);
+        remaining = tail;
+    }
+
+    Ok(timestamps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::tests::spread_all;
+
+    #[test]
+    fn fixtures() {
+        let timestamps = [1000, 1060, 1120, 1181, 1240, 1240];
+        let mut out = vec![42];
+        assert_eq!(encode_timestamps(&timestamps, &mut out), 8);
+        assert_eq!(out, vec![42, 249, 3, 232, 120, 0, 2, 3, 117]);
+        assert_eq!(decode_timestamps(&out[1..]), Ok(timestamps.to_vec()));
+
+        out.clear();
+        assert_eq!(encode_timestamps(&[5, 0, u64::MAX], &mut out), 3);
+        assert_eq!(out, vec![5, 9, 8]);
+        assert_eq!(decode_timestamps(&out), Ok(vec![5, 0, u64::MAX]));
+
+        assert_eq!(decode_timestamps(&[7, 248, 1]), Err((NonCanonical(1), 1)));
+        assert_eq!(decode_timestamps(&[]), Ok(vec![]));
+    }
+
+    quickcheck! {
+        fn test_roundtrip(timestamps: Vec<u64>) -> bool {
+            let timestamps = spread_all(&timestamps);
+            let mut out = Vec::new();
+            encode_timestamps(&timestamps, &mut out) == out.len() &&
+            decode_timestamps(&out) == Ok(timestamps)
+        }
+    }
+}
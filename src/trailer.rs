@@ -0,0 +1,121 @@
+//! Trailers: varu64s that are decoded from the end of a buffer.
+//!
+//! A regular encoding can not be found from the end of a buffer, since the length is stored in
+//! its first byte. A trailer stores the same bytes in a different order: the big-endian payload
+//! first, followed by the tag byte. Records that end with a trailer holding their length can be
+//! walked backwards without an index.
+
+use super::{decoded_len, encode, encoding_length, DecodeError};
+
+/// Encodes `n` as a trailer into the output buffer, returning how many bytes have been written.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_trailer(n: u64, out: &mut [u8]) -> usize {
+    let len = encoding_length(n);
+    let mut tmp = [0u8; 9];
+    encode(n, &mut tmp[..]);
+
+    out[..len - 1].copy_from_slice(&tmp[1..len]);
+    out[len - 1] = tmp[0];
+    len
+}
+
+/// Decode the trailer at the end of the `input` buffer, returning the number and the length of
+/// the trailer.
+///
+/// # Errors
+/// Returns `UnexpectedEndOfInput` if the `input` is shorter than the trailer, and
+/// `NonCanonical` if the trailer is not the shortest possible one for the number.
+pub fn decode_last(input: &[u8]) -> Result<(u64, usize), DecodeError> {
+    let tag = match input.last() {
+        Some(tag) => *tag,
+        None => return Err(DecodeError::UnexpectedEndOfInput(1)),
+    };
+
+    let len = decoded_len(tag);
+    if input.len() < len {
+        return Err(DecodeError::UnexpectedEndOfInput(len - input.len()));
+    }
+    if len == 1 {
+        return Ok((tag as u64, 1));
+    }
+
+    let mut n = 0;
+    for b in input[input.len() - len..input.len() - 1].iter() {
+        n = (n << 8) | *b as u64;
+    }
+
+    if encoding_length(n) < len {
+        Err(DecodeError::NonCanonical(n))
+    } else {
+        Ok((n, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let mut buf = [0u8; 9];
+        assert_eq!(encode_trailer(7, &mut buf[..]), 1);
+        assert_eq!(buf[0], 7);
+        assert_eq!(encode_trailer(256, &mut buf[..]), 3);
+        assert_eq!(&buf[..3], &[1, 0, 249]);
+
+        assert_eq!(decode_last(&[5, 1, 0, 249]), Ok((256, 3)));
+        assert_eq!(decode_last(&[1, 0, 7]), Ok((7, 1)));
+        assert_eq!(decode_last(&[0, 1, 248]), Err(NonCanonical(1)));
+        assert_eq!(decode_last(&[0, 249]), Err(UnexpectedEndOfInput(1)));
+        assert_eq!(decode_last(&[]), Err(UnexpectedEndOfInput(1)));
+
+        // A long record followed by a short one.
+        let mut data = vec![42; 300];
+        data.extend_from_slice(&[1, 44, 249, 7, 8, 2]);
+        assert_eq!(decode_last(&data), Ok((2, 1)));
+        assert_eq!(decode_last(&data[..data.len() - 3]), Ok((300, 3)));
+    }
+
+    quickcheck! {
+        fn test_walk_backwards(records: Vec<(Vec<u8>, u16)>) -> bool {
+            // Pad some records to 248 bytes or more, so that they get multi-byte trailers.
+            let records: Vec<Vec<u8>> = records
+                .iter()
+                .map(|(record, pad)| {
+                         let mut record = record.clone();
+                         record.resize(record.len() + 248 * (*pad as usize % 3), 42);
+                         record
+                     })
+                .collect();
+            let mut data = Vec::new();
+            for record in records.iter() {
+                data.extend_from_slice(record);
+                let mut buf = [0u8; 9];
+                let len = encode_trailer(record.len() as u64, &mut buf[..]);
+                data.extend_from_slice(&buf[..len]);
+            }
+
+            let mut end = data.len();
+            for record in records.iter().rev() {
+                let (len, trailer_len) = decode_last(&data[..end]).unwrap();
+                let start = end - trailer_len - len as usize;
+                assert_eq!(&data[start..end - trailer_len], &record[..]);
+                end = start;
+            }
+            end == 0
+        }
+
+        fn test_trailer_roundtrip(n: u64, shift: u8, prefix: Vec<u8>) -> bool {
+            let n = n.rotate_left(shift as u32);
+            let mut data = prefix.clone();
+            let mut buf = [0u8; 9];
+            let len = encode_trailer(n, &mut buf[..]);
+            data.extend_from_slice(&buf[..len]);
+
+            len == encoding_length(n) && decode_last(&data) == Ok((n, len))
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Validation of buffers of concatenated varu64s.
+
+use std::ops::Range;
+
+use super::DecodeError;
+use super::source::{decode_from, Source, SourceError};
+
+/// A problem found while validating a buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Issue {
+    /// What is wrong with the data.
+    pub error: DecodeError,
+    /// The bytes affected by the problem, as offsets into the validated buffer.
+    pub range: Range<usize>,
+}
+
+/// The result of validating a buffer with `validate_report`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ValidationReport {
+    /// How many valid varu64s were found.
+    pub values: usize,
+    /// The problems that were found, in the order they occur in the buffer.
+    pub issues: Vec<Issue>,
+    /// Whether the whole buffer was validated, `false` if validation stopped because the
+    /// maximum number of issues was reached.
+    pub complete: bool,
+}
+
+impl ValidationReport {
+    /// Whether the buffer consists of valid varu64s only.
+    pub fn is_valid(&self) -> bool {
+        self.complete && self.issues.is_empty()
+    }
+}
+
+/// Validate a buffer of concatenated varu64s, reporting all problems rather than only the first
+/// one. Validation stops when encountering a problem after `max_issues` problems have already
+/// been found.
+///
+/// A noncanonical encoding is reported with the range of the full encoding, and validation
+/// continues after it, at the offset given by the length in its first byte. If a corrupt tag
+/// byte made an encoding swallow valid data, this can not be detected. To salvage data after a
+/// corrupt region instead, search for an offset from which decoding succeeds with `resync`,
+/// `resync_min` or `RecoveringDecoder`. An encoding cut off by the end of the buffer is reported
+/// with the range from its first byte to the end of the buffer.
+pub fn validate_report(input: &[u8], max_issues: usize) -> ValidationReport {
+    match validate_source(&mut &input[..], max_issues) {
+        Ok(report) => report,
+        Err(never) => match never {},
+    }
+}
+
+/// Validate the concatenated varu64s of a source like `validate_report`, with ranges given as
+/// offsets from the position of the source at the time of the call.
+///
+/// # Errors
+/// Returns the first error reported by the source.
+pub fn validate_source<S: Source + ?Sized>(s: &mut S,
+                                           max_issues: usize)
+                                           -> Result<ValidationReport, S::Error> {
+    let mut report = ValidationReport::default();
+    let mut s = Counted { s, count: 0 };
+
+    loop {
+        let start = s.count;
+        match decode_from(&mut s) {
+            Ok(None) => break,
+            Ok(Some(_)) => report.values += 1,
+            Err(SourceError::Decode(error)) => {
+                if report.issues.len() == max_issues {
+                    return Ok(report);
+                }
+
+                report.issues.push(Issue {
+                                       error,
+                                       range: start..s.count,
+                                   })
+            }
+            Err(SourceError::Source(e)) => return Err(e),
+        }
+    }
+
+    report.complete = true;
+    Ok(report)
+}
+
+// A source counting the bytes read from it.
+struct Counted<'a, S: 'a + ?Sized> {
+    s: &'a mut S,
+    count: usize,
+}
+
+impl<'a, S: Source + ?Sized> Source for Counted<'a, S> {
+    type Error = S::Error;
+
+    fn next_byte(&mut self) -> Result<Option<u8>, S::Error> {
+        let b = self.s.next_byte()?;
+        if b.is_some() {
+            self.count += 1;
+        }
+        Ok(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+    use super::super::source::{ChunksSource, ReadSource};
+
+    #[test]
+    fn fixtures() {
+        assert!(validate_report(&[], 0).is_valid());
+        assert!(validate_report(&[1, 249, 1, 0], 0).is_valid());
+
+        let report = validate_report(&[1, 248, 2, 3, 249, 0, 7, 4, 250, 1], 10);
+        assert_eq!(report,
+                   ValidationReport {
+                       values: 3,
+                       issues: vec![Issue {
+                                        error: NonCanonical(2),
+                                        range: 1..3,
+                                    },
+                                    Issue {
+                                        error: NonCanonical(7),
+                                        range: 4..7,
+                                    },
+                                    Issue {
+                                        error: UnexpectedEndOfInput(2),
+                                        range: 8..10,
+                                    }],
+                       complete: true,
+                   });
+
+        let report = validate_report(&[1, 248, 2, 3, 249, 0, 7, 4, 250, 1], 1);
+        assert_eq!(report.values, 2);
+        assert_eq!(report.issues.len(), 1);
+        assert!(!report.complete);
+
+        let chunks = [&[1, 248][..], &[2, 3, 249, 0][..], &[7, 4, 250, 1][..]];
+        let report = validate_source(&mut ChunksSource::new(&chunks), 10).unwrap();
+        assert_eq!(report, validate_report(&[1, 248, 2, 3, 249, 0, 7, 4, 250, 1], 10));
+
+        let mut r = ReadSource(&[249, 1, 0, 248][..]);
+        let report = validate_source(&mut r, 10).unwrap();
+        assert_eq!(report.values, 1);
+        assert_eq!(report.issues,
+                   vec![Issue {
+                            error: UnexpectedEndOfInput(1),
+                            range: 3..4,
+                        }]);
+    }
+}
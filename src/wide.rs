@@ -0,0 +1,66 @@
+//! 128 bit unsigned integers, available with the `u128` feature.
+//!
+//! This is an extension and not part of the varu64 spec. A `u128` is encoded as two varu64s:
+//! the most significant 64 bits followed by the least significant 64 bits. Both must be
+//! canonical, so every number has exactly one encoding, between two and eighteen bytes long.
+
+use super::{decode, encode, encoding_length, DecodeError};
+
+/// Return how many bytes the encoding of `n` will take up.
+pub fn encoding_length_u128(n: u128) -> usize {
+    encoding_length((n >> 64) as u64) + encoding_length(n as u64)
+}
+
+/// Encodes `n` into the output buffer, returning how many bytes have been written.
+///
+/// # Panics
+/// Panics if the buffer is not large enough to hold the encoding.
+pub fn encode_u128(n: u128, out: &mut [u8]) -> usize {
+    let len = encode((n >> 64) as u64, out);
+    len + encode(n as u64, &mut out[len..])
+}
+
+/// Decode a `u128` from the `input` buffer, returning the number and the remaining bytes.
+///
+/// # Errors
+/// Errors in either half are reported as by `decode`. A `NonCanonical` error contains the
+/// noncanonically encoded half.
+pub fn decode_u128(input: &[u8]) -> Result<(u128, &[u8]), (DecodeError, &[u8])> {
+    let (high, tail) = decode(input)?;
+    let (low, tail) = decode(tail)?;
+    Ok(((high as u128) << 64 | low as u128, tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DecodeError::*;
+
+    #[test]
+    fn fixtures() {
+        let mut buf = [0u8; 18];
+        assert_eq!(encode_u128(7, &mut buf[..]), 2);
+        assert_eq!(&buf[..2], &[0, 7]);
+        assert_eq!(encode_u128(1 << 64 | 300, &mut buf[..]), 4);
+        assert_eq!(&buf[..4], &[1, 249, 1, 44]);
+        assert_eq!(encoding_length_u128(u128::MAX), 18);
+        assert_eq!(encode_u128(u128::MAX, &mut buf[..]), 18);
+
+        assert_eq!(decode_u128(&[1, 249, 1, 44, 5]), Ok((1 << 64 | 300, &[5][..])));
+        assert_eq!(decode_u128(&[248, 1, 0]), Err((NonCanonical(1), &[0][..])));
+        assert_eq!(decode_u128(&[0, 248, 1]), Err((NonCanonical(1), &[][..])));
+        assert_eq!(decode_u128(&[0]), Err((UnexpectedEndOfInput(1), &[][..])));
+    }
+
+    quickcheck! {
+        fn test_roundtrip(high: u64, low: u64, shift: u8, tail: Vec<u8>) -> bool {
+            let n = ((high as u128) << 64 | low as u128).rotate_left(shift as u32);
+            let mut buf = vec![0u8; 18];
+            let len = encode_u128(n, &mut buf[..]);
+            buf.truncate(len);
+            buf.extend_from_slice(&tail);
+
+            len == encoding_length_u128(n) && decode_u128(&buf) == Ok((n, &tail[..]))
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Buffered encoding into `std::io` writers.
+
+use std::{io, thread};
+
+use super::encode;
+
+// The default capacity of the internal buffer.
+const DEFAULT_CAPACITY: usize = 8192;
+
+/// A writer wrapper that buffers encoded varu64s, only passing them on to the inner writer in
+/// large chunks.
+///
+/// Unlike wrapping a writer in a `BufWriter` and calling `encode_write` on it, values are
+/// encoded directly into the buffer. Any buffered data is flushed when the `VarU64BufWriter` is
+/// dropped, unless the thread is panicking, but errors that happen during this are ignored.
+/// Call `flush` or `into_inner` to observe them.
+#[derive(Debug)]
+pub struct VarU64BufWriter<W: io::Write> {
+    inner: Option<W>, // Only `None` after `into_inner` has been called.
+    buf: Vec<u8>,
+    capacity: usize,
+    values: u64, // How many values have been written in total.
+    flushed: u64, // How many bytes have been passed to the inner writer in total.
+}
+
+impl<W: io::Write> VarU64BufWriter<W> {
+    /// Create a new `VarU64BufWriter` with a default buffer capacity.
+    pub fn new(inner: W) -> VarU64BufWriter<W> {
+        VarU64BufWriter::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+
+    /// Create a new `VarU64BufWriter` whose buffer holds at least `capacity` bytes.
+    ///
+    /// The capacity is rounded up to 9 bytes so that any varu64 fits into the buffer.
+    pub fn with_capacity(capacity: usize, inner: W) -> VarU64BufWriter<W> {
+        let capacity = if capacity < 9 { 9 } else { capacity };
+        VarU64BufWriter {
+            inner: Some(inner),
+            buf: Vec::with_capacity(capacity),
+            capacity,
+            values: 0,
+            flushed: 0,
+        }
+    }
+
+    /// Encode `n` into the buffer, returning how many bytes the encoding takes up.
+    ///
+    /// This only writes to the inner writer if the buffer does not have enough space left, in
+    /// which case the whole buffer is written.
+    pub fn write_u64(&mut self, n: u64) -> io::Result<usize> {
+        if self.buf.len() + 9 > self.capacity {
+            self.flush_buf()?;
+        }
+
+        let len = self.buf.len();
+        self.buf.resize(len + 9, 0);
+        let written = encode(n, &mut self.buf[len..]);
+        self.buf.truncate(len + written);
+
+        self.values += 1;
+        Ok(written)
+    }
+
+    /// Encode all `values` into the buffer, returning how many bytes their encodings take up.
+    ///
+    /// Like `write_u64`, this only writes to the inner writer whenever the buffer runs full.
+    pub fn write_all_u64s(&mut self, values: &[u64]) -> io::Result<usize> {
+        let mut written = 0;
+        for n in values.iter() {
+            written += self.write_u64(*n)?;
+        }
+        Ok(written)
+    }
+
+    /// Write all buffered data to the inner writer, then flush the inner writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flush_buf()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+
+    // Write all buffered data to the inner writer. On error, the data that has been written is
+    // removed from the buffer nonetheless, so that it is not written again.
+    fn flush_buf(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < self.buf.len() {
+            match self.inner.as_mut().unwrap().write(&self.buf[written..]) {
+                Ok(0) => {
+                    result = Err(io::Error::new(io::ErrorKind::WriteZero,
+                                                "failed to write the buffered data"));
+                    break;
+                }
+                Ok(n) => written += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        self.flushed += written as u64;
+        self.buf.drain(..written);
+        result
+    }
+
+    /// How many values have been written so far.
+    pub fn values(&self) -> u64 {
+        self.values
+    }
+
+    /// How many bytes are currently buffered.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// How many bytes have been passed on to the inner writer so far.
+    pub fn flushed(&self) -> u64 {
+        self.flushed
+    }
+
+    /// Get a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Get a mutable reference to the inner writer.
+    ///
+    /// Writing directly to the inner writer may interleave data with buffered values.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Flush all buffered data, then return the inner writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    /// Finish writing: flush all buffered data, then return the inner writer.
+    ///
+    /// This is the same as `into_inner`.
+    pub fn finish(self) -> io::Result<W> {
+        self.into_inner()
+    }
+}
+
+/// The counterpart to `reader::Varu64Reader`.
+pub type Varu64Writer<W> = VarU64BufWriter<W>;
+
+impl<W: io::Write> Drop for VarU64BufWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() && !thread::panicking() {
+            let _ = self.flush_buf();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use super::super::decode;
+    use super::super::tests::spread_all;
+
+    // A writer that records the size of every write call.
+    struct CountingWriter {
+        data: Vec<u8>,
+        writes: Vec<usize>,
+    }
+
+    impl io::Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.extend_from_slice(buf);
+            self.writes.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // A writer that accepts two bytes, then fails once.
+    struct FailingWriter {
+        data: Vec<u8>,
+        calls: usize,
+    }
+
+    impl io::Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            match self.calls {
+                1 => {
+                    let len = ::std::cmp::min(2, buf.len());
+                    self.data.extend_from_slice(&buf[..len]);
+                    Ok(len)
+                }
+                2 => Err(io::Error::other("failing")),
+                _ => self.data.write(buf),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_partial_flush() {
+        let inner = FailingWriter {
+            data: Vec::new(),
+            calls: 0,
+        };
+        let mut w = VarU64BufWriter::new(inner);
+        w.write_all_u64s(&[7, 300, 8]).unwrap();
+        assert_eq!(w.flush().unwrap_err().kind(), io::ErrorKind::Other);
+        assert_eq!(w.buffered(), 3);
+        assert_eq!(w.flushed(), 2);
+
+        let inner = w.into_inner().unwrap();
+        assert_eq!(inner.data, vec![7, 249, 1, 44, 8]);
+    }
+
+    #[test]
+    fn no_flush_while_panicking() {
+        let mut data = Vec::new();
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            let mut w = VarU64BufWriter::new(&mut data);
+            w.write_u64(7).unwrap();
+            panic!("unwinding");
+        }));
+        assert!(result.is_err());
+        assert!(data.is_empty());
+    }
+
+    quickcheck! {
+        fn test_buf_writer(values: Vec<u64>, capacity: u8) -> bool {
+            let values = spread_all(&values);
+            let inner = CountingWriter {
+                data: Vec::new(),
+                writes: Vec::new(),
+            };
+            let mut w = VarU64BufWriter::with_capacity(capacity as usize, inner);
+
+            let mut total = 0;
+            for n in values.iter() {
+                total += w.write_u64(*n).unwrap();
+                assert!(w.buffered() <= w.capacity);
+            }
+            assert_eq!(w.values(), values.len() as u64);
+            assert_eq!(w.flushed() + w.buffered() as u64, total as u64);
+
+            let inner = w.into_inner().unwrap();
+            assert_eq!(inner.data.len(), total);
+            for (i, len) in inner.writes.iter().enumerate() {
+                if i + 1 < inner.writes.len() {
+                    assert!(len + 9 > ::std::cmp::max(capacity as usize, 9));
+                }
+            }
+
+            let mut data = &inner.data[..];
+            for n in values.iter() {
+                let (dec, tail) = decode(data).unwrap();
+                assert_eq!(dec, *n);
+                data = tail;
+            }
+            assert!(data.is_empty());
+
+            true
+        }
+    }
+
+    #[test]
+    fn flush_on_drop() {
+        let mut data = Vec::new();
+        {
+            let mut w = VarU64BufWriter::new(&mut data);
+            w.write_u64(300).unwrap();
+            assert_eq!(w.buffered(), 3);
+            assert_eq!(w.flushed(), 0);
+        }
+        assert_eq!(data, vec![249, 1, 44]);
+    }
+
+    #[test]
+    fn test_write_all_u64s() {
+        let mut w = Varu64Writer::with_capacity(0, Vec::new());
+        assert_eq!(w.write_all_u64s(&[1, 300, 248, u64::MAX]).unwrap(), 15);
+        assert_eq!(w.values(), 4);
+        assert_eq!(w.buffered(), 9);
+        assert_eq!(w.flushed(), 6);
+        assert_eq!(w.write_all_u64s(&[]).unwrap(), 0);
+
+        let data = w.finish().unwrap();
+        assert_eq!(data,
+                   vec![1, 249, 1, 44, 248, 248, 255, 255, 255, 255, 255, 255, 255, 255, 255]);
+    }
+}